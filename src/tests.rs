@@ -1,28 +1,15 @@
 use crate::VoxelCSG;
-use grid_tree::{NodePtr, VisitCommand};
 
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
     use super::*;
     use grid_tree::glam::IVec3;
+    use crate::Cancelled;
 
     /// A helper to count how many leaf voxels are `true` in the entire octree.
     fn count_filled_voxels(csg: &VoxelCSG) -> usize {
-        let mut count = 0;
-        for (root_key, root_node) in csg.tree.iter_roots() {
-            let root_ptr = NodePtr::new(root_key.level, root_node.self_ptr);
-            csg.tree.visit_tree_depth_first(root_ptr, root_key.coordinates, 0, |ptr, _coords| {
-                if ptr.level() == 0 {
-                    if let Some(&val) = csg.tree.get_value(ptr) {
-                        if val {
-                            count += 1;
-                        }
-                    }
-                }
-                VisitCommand::Continue
-            });
-        }
-        count
+        csg.count_filled()
     }
 
     /// Asserts that each voxel in `coords_set` is `true` and all others
@@ -57,7 +44,7 @@ mod tests {
     fn test_new_voxel_csg() {
         let height = 4;
         let csg = VoxelCSG::new(height);
-        assert_eq!(csg.tree.height(), height as u8);
+        assert_eq!(csg.height(), height);
         // Initially, it should have no filled nodes
         assert_eq!(count_filled_voxels(&csg), 0);
     }
@@ -162,9 +149,9 @@ mod tests {
 
         // Because the stub uses `(x + y + z) % 2 == 0` as "inside," let's
         // just spot check that some coords match:
-        assert_eq!(csg.get_voxel(IVec3::new(0,0,0)), true);
-        assert_eq!(csg.get_voxel(IVec3::new(1,0,0)), false);
-        assert_eq!(csg.get_voxel(IVec3::new(-1,1,0)), true);
+        assert!(csg.get_voxel(IVec3::new(0,0,0)));
+        assert!(!csg.get_voxel(IVec3::new(1,0,0)));
+        assert!(csg.get_voxel(IVec3::new(-1,1,0)));
     }
 
     // ------------------------------------------------------------
@@ -190,8 +177,8 @@ mod tests {
         assert!(union_csg.get_voxel(IVec3::new(1,1,0)));
         // (2,1,1) only in csg2 => also in union.
         assert!(union_csg.get_voxel(IVec3::new(2,1,1)));
-        // (3,1,1) only in csg2 => also in union.
-        assert!(union_csg.get_voxel(IVec3::new(3,1,1)));
+        // (3,1,1) is past csg2's exclusive max => in neither shape.
+        assert!(!union_csg.get_voxel(IVec3::new(3,1,1)));
         // (0,0,0) only in csg1 => in union
         assert!(union_csg.get_voxel(IVec3::new(0,0,0)));
     }
@@ -246,26 +233,16 @@ mod tests {
         // Fill a small set of voxels:
         csg.fill_cube(IVec3::new(0,0,0), IVec3::new(2,2,1)); 
         // That's 2*2*1=4 voxels: (0,0,0), (0,1,0), (1,0,0), (1,1,0)
-        assert_eq!(count_filled_voxels(&csg), 4);
+        csg.set_voxel(IVec3::new(5,4,3), true);
+        let filled = csg.count_filled();
+        let (min, max) = csg.bounding_box().unwrap();
+        let size = max - min + IVec3::ONE;
+        let bbox_volume = (size.x * size.y * size.z) as usize;
 
-        // Invert in place
+        // Invert in place: the complement is taken within the bounding box
+        // [(0,0,0), (5,4,3)].
         csg.invert_in_place();
-
-        // Now the previously "true" nodes become false, and all other *existing* nodes become true.
-        // Since we only had 4 leaf nodes allocated and they were "true," each flips to "false."
-        // So effectively, we expect all 4 to become "false."
-        // However, note that "invert_in_place" will only flip *existing allocated nodes* in the tree.
-        // This might leave 4 nodes that are now "true = false => flipped to false" 
-        // or they might remain allocated as "false." 
-        // If your implementation prunes them automatically, the total count is 0. 
-        // If it doesn't prune them, we still have 4 nodes, but all are "false."
-
-        let new_count = count_filled_voxels(&csg);
-        assert_eq!(
-            new_count, 
-            0,
-            "After inverting, we expect 0 'true' voxels if newly-false nodes are not replaced."
-        );
+        assert_eq!(count_filled_voxels(&csg), bbox_volume - filled);
 
         // Confirm each voxel we had is now false:
         for x in 0..2 {
@@ -273,6 +250,13 @@ mod tests {
                 assert!(!csg.get_voxel(IVec3::new(x,y,0)));
             }
         }
+        assert!(!csg.get_voxel(IVec3::new(5,4,3)));
+        assert!(csg.get_voxel(IVec3::new(2,0,0)));
+        assert!(csg.get_voxel(IVec3::new(5,4,2)));
+        // Outside the bounding box nothing is filled.
+        assert!(!csg.get_voxel(IVec3::new(6,0,0)));
+        assert!(!csg.get_voxel(IVec3::new(-1,0,0)));
+        assert!(!csg.get_voxel(IVec3::new(0,0,4)));
     }
 
     #[test]
@@ -280,12 +264,37 @@ mod tests {
         // This test verifies the non-in-place version that returns a new shape.
         let mut csg = VoxelCSG::new(4);
         csg.fill_cube(IVec3::new(-1,-1,0), IVec3::new(1,1,1)); // 2*2*1 = 4 voxels
+        csg.fill_sphere(IVec3::new(20,0,0), 3.0);
+        let filled = count_filled_voxels(&csg);
 
         let inverted = csg.invert();
         // Original is unchanged:
-        assert_eq!(count_filled_voxels(&csg), 4);
-        // Inverted result should have 0 "true" (assuming no new nodes are allocated for previously non-existent coords).
-        assert_eq!(count_filled_voxels(&inverted), 0);
+        assert_eq!(count_filled_voxels(&csg), filled);
+        let (min, max) = csg.bounding_box().unwrap();
+        let size = max - min + IVec3::ONE;
+        assert_eq!(inverted.count_filled(), (size.x * size.y * size.z) as usize - filled);
+        assert!(!inverted.get_voxel(IVec3::new(-1,-1,0)));
+        assert!(inverted.get_voxel(IVec3::new(5,0,0)));
+        assert!(!inverted.get_voxel(IVec3::new(-2,-1,0)));
+
+        // An empty shape has no bounding box and stays empty.
+        assert_eq!(VoxelCSG::new(4).invert().count_filled(), 0);
+    }
+
+    #[test]
+    fn test_invert_twice() {
+        // Every face of a sphere's bounding box has empty voxels at its
+        // corners, so the inverted shape spans the same box.
+        let mut csg = VoxelCSG::new(5);
+        csg.fill_sphere(IVec3::new(3,4,5), 4.0);
+        csg.set_voxel(IVec3::new(3,4,5), false);
+        assert_eq!(csg.invert().bounding_box(), csg.bounding_box());
+        assert_eq!(csg.invert().invert(), csg);
+
+        // A solid box fills its own bounding box, so it inverts to nothing.
+        let mut solid = VoxelCSG::new(4);
+        solid.fill_cube(IVec3::new(0,0,0), IVec3::new(8,8,8));
+        assert_eq!(solid.invert().count_filled(), 0);
     }
 
     // ------------------------------------------------------------
@@ -340,5 +349,2677 @@ mod tests {
         // Optionally, clean up the file if you wish, e.g.
         // std::fs::remove_file(path).ok();
     }
+
+    // ------------------------------------------------------------
+    // 6) Progress reporting
+    // ------------------------------------------------------------
+    #[test]
+    fn test_fill_cube_with_progress() {
+        let mut csg = VoxelCSG::new(4);
+        let mut reports = Vec::new();
+        csg.fill_cube_with_progress(IVec3::new(0,0,0), IVec3::new(4,4,4), |f| reports.push(f));
+
+        // The fill itself is unchanged.
+        assert_eq!(count_filled_voxels(&csg), 64);
+
+        // Called at least once, never decreasing, ending near 1.0.
+        assert!(!reports.is_empty());
+        assert!(reports.windows(2).all(|w| w[0] <= w[1]));
+        assert!((reports.last().unwrap() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_csg_with_progress() {
+        let mut csg1 = VoxelCSG::new(4);
+        csg1.fill_cube(IVec3::new(0,0,0), IVec3::new(3,3,3));
+        let mut csg2 = VoxelCSG::new(4);
+        csg2.fill_cube(IVec3::new(1,1,0), IVec3::new(3,3,3));
+
+        let mut union_calls = 0;
+        let mut last = 0.0;
+        let union_csg = csg1.union_with_progress(&csg2, |f| { union_calls += 1; last = f; });
+        assert!(union_calls >= 1);
+        assert!((last - 1.0f32).abs() < 1e-6);
+        assert_eq!(count_filled_voxels(&union_csg), count_filled_voxels(&csg1.union(&csg2)));
+
+        let mut diff_calls = 0;
+        let diff_csg = csg1.difference_with_progress(&csg2, |f| { diff_calls += 1; last = f; });
+        assert!(diff_calls >= 1);
+        assert!((last - 1.0f32).abs() < 1e-6);
+        assert_eq!(count_filled_voxels(&diff_csg), 15);
+
+        // Larger shapes report several increasing fractions, and the result
+        // matches the plain operation node for node.
+        let mut big1 = VoxelCSG::new(7);
+        big1.fill_sphere(IVec3::new(3,4,5), 12.0);
+        let mut big2 = VoxelCSG::new(7);
+        big2.fill_cube(IVec3::new(-20,-7,2), IVec3::new(5,12,14));
+        let mut reports = Vec::new();
+        let diff_csg = big1.difference_with_progress(&big2, |f| reports.push(f));
+        assert!(reports.len() > 2);
+        assert!(reports.windows(2).all(|w| w[0] <= w[1] && w[1] <= 1.0));
+        assert_eq!(diff_csg.node_count(), big1.difference(&big2).node_count());
+        reports.clear();
+        let union_csg = big1.union_with_progress(&big2, |f| reports.push(f));
+        assert!(reports.len() > 2);
+        assert!(reports.windows(2).all(|w| w[0] <= w[1] && w[1] <= 1.0));
+        assert_eq!(union_csg.node_count(), big1.union(&big2).node_count());
+    }
+
+    // ------------------------------------------------------------
+    // 7) Cancellation
+    // ------------------------------------------------------------
+    #[test]
+    fn test_fill_cube_cancellable() {
+        // An unaligned box is filled in many regions. The first check comes
+        // before anything is filled, later ones every 4096 voxels; cancel at
+        // the third.
+        let (min, max) = (IVec3::new(-5,-3,1), IVec3::new(40,37,29));
+        let mut csg = VoxelCSG::new(7);
+        let checks = std::cell::Cell::new(0);
+        let result = csg.fill_cube_cancellable(min, max, || {
+            checks.set(checks.get() + 1);
+            checks.get() > 2
+        });
+
+        assert_eq!(result, Err(Cancelled));
+        assert_eq!(checks.get(), 3);
+        let filled = count_filled_voxels(&csg);
+        assert!((2 * 4096..45 * 40 * 28).contains(&filled), "Cancelled fill should be partial, got {}", filled);
+
+        // Cancelling up front fills nothing.
+        let mut untouched = VoxelCSG::new(7);
+        assert_eq!(untouched.fill_cube_cancellable(min, max, || true), Err(Cancelled));
+        assert_eq!(count_filled_voxels(&untouched), 0);
+
+        // Never cancelling builds the same tree as `fill_cube`.
+        let mut full = VoxelCSG::new(7);
+        assert!(full.fill_cube_cancellable(min, max, || false).is_ok());
+        let mut plain = VoxelCSG::new(7);
+        plain.fill_cube(min, max);
+        assert_eq!(full, plain);
+        assert_eq!(full.node_count(), plain.node_count());
+    }
+
+    #[test]
+    fn test_union_cancellable() {
+        let mut csg1 = VoxelCSG::new(4);
+        csg1.fill_cube(IVec3::new(0,0,0), IVec3::new(2,2,2));
+        let mut csg2 = VoxelCSG::new(4);
+        csg2.fill_cube(IVec3::new(1,1,0), IVec3::new(3,2,2));
+
+        assert!(matches!(csg1.union_cancellable(&csg2, || true), Err(Cancelled)));
+
+        let union_csg = csg1.union_cancellable(&csg2, || false).unwrap();
+        assert_eq!(count_filled_voxels(&union_csg), 10);
+
+        // Large unaligned shapes: cancelled partway, or the same tree as `union`.
+        let mut big1 = VoxelCSG::new(7);
+        big1.fill_sphere(IVec3::new(3,4,5), 12.0);
+        let mut big2 = VoxelCSG::new(7);
+        big2.fill_cube(IVec3::new(-20,-7,2), IVec3::new(5,12,14));
+        let checks = std::cell::Cell::new(0);
+        let cancelled = big1.union_cancellable(&big2, || {
+            checks.set(checks.get() + 1);
+            checks.get() > 2
+        });
+        assert!(matches!(cancelled, Err(Cancelled)));
+        assert_eq!(checks.get(), 3);
+        let union_csg = big1.union_cancellable(&big2, || false).unwrap();
+        assert_eq!(union_csg, big1.union(&big2));
+        assert_eq!(union_csg.node_count(), big1.union(&big2).node_count());
+    }
+
+    // ------------------------------------------------------------
+    // 8) Additional primitives
+    // ------------------------------------------------------------
+    #[test]
+    fn test_fill_sphere_shell() {
+        let mut csg = VoxelCSG::new(5);
+        csg.fill_sphere_shell(IVec3::new(0,0,0), 5.0, 3.0);
+
+        // Hollow center:
+        assert!(!csg.get_voxel(IVec3::new(0,0,0)));
+        assert!(!csg.get_voxel(IVec3::new(2,0,0)));
+        // Mid-shell:
+        assert!(csg.get_voxel(IVec3::new(4,0,0)));
+        assert!(csg.get_voxel(IVec3::new(0,-4,0)));
+        // Outside the outer radius:
+        assert!(!csg.get_voxel(IVec3::new(6,0,0)));
+        assert!(!csg.get_voxel(IVec3::new(4,4,0)));
+
+        // Degenerate radii fill nothing.
+        let mut empty = VoxelCSG::new(5);
+        empty.fill_sphere_shell(IVec3::new(0,0,0), 3.0, 3.0);
+        assert_eq!(count_filled_voxels(&empty), 0);
+    }
+
+    #[test]
+    fn test_fill_rounded_box() {
+        let min = IVec3::new(0,0,0);
+        let max = IVec3::new(10,10,10);
+
+        let mut sharp = VoxelCSG::new(5);
+        sharp.fill_cube(min, max);
+        let mut rounded = VoxelCSG::new(5);
+        rounded.fill_rounded_box(min, max, 3.0);
+
+        // Corners are shaved off, the faces' centers are kept.
+        assert!(count_filled_voxels(&rounded) < count_filled_voxels(&sharp));
+        assert!(!rounded.get_voxel(IVec3::new(0,0,0)));
+        assert!(!rounded.get_voxel(IVec3::new(9,9,9)));
+        assert!(rounded.get_voxel(IVec3::new(5,5,0)));
+        assert!(rounded.get_voxel(IVec3::new(5,5,5)));
+
+        // A zero radius is the same as a plain cube.
+        let mut zero = VoxelCSG::new(5);
+        zero.fill_rounded_box(min, max, 0.0);
+        assert_eq!(count_filled_voxels(&zero), count_filled_voxels(&sharp));
+    }
+
+    // ------------------------------------------------------------
+    // 9) Transforms
+    // ------------------------------------------------------------
+    #[test]
+    fn test_scale() {
+        let mut csg = VoxelCSG::new(4);
+        csg.fill_cube(IVec3::new(1,1,1), IVec3::new(2,2,2)); // single voxel at (1,1,1)
+
+        let scaled = csg.scale(IVec3::new(2,1,3));
+        assert_eq!(count_filled_voxels(&scaled), 6);
+
+        // The voxel maps to the block x in [2,4), y in [1,2), z in [3,6).
+        let expected: std::collections::HashSet<IVec3> = (3..6)
+            .flat_map(|z| (2..4).map(move |x| IVec3::new(x, 1, z)))
+            .collect();
+        assert_voxels_match(&scaled, &expected, IVec3::new(0,0,0), IVec3::new(7,7,7));
+    }
+
+    // ------------------------------------------------------------
+    // 10) Neighborhood queries
+    // ------------------------------------------------------------
+    #[test]
+    fn test_neighbors() {
+        let mut single = VoxelCSG::new(4);
+        single.fill_cube(IVec3::new(0,0,0), IVec3::new(1,1,1));
+        assert_eq!(single.neighbors6(IVec3::new(0,0,0)), [false; 6]);
+        assert_eq!(single.neighbors26(IVec3::new(0,0,0)), [false; 26]);
+
+        let mut block = VoxelCSG::new(4);
+        block.fill_cube(IVec3::new(0,0,0), IVec3::new(3,3,3));
+        assert_eq!(block.neighbors6(IVec3::new(1,1,1)), [true; 6]);
+        assert_eq!(block.neighbors26(IVec3::new(1,1,1)), [true; 26]);
+
+        // On a corner, only the +X/+Y/+Z face neighbors are filled.
+        assert_eq!(
+            block.neighbors6(IVec3::new(0,0,0)),
+            [false, true, false, true, false, true]
+        );
+    }
+
+    // ------------------------------------------------------------
+    // 11) In-place CSG operations
+    // ------------------------------------------------------------
+    #[test]
+    fn test_union_with() {
+        let mut csg1 = VoxelCSG::new(4);
+        csg1.fill_cube(IVec3::new(0,0,0), IVec3::new(2,2,2));
+        let mut csg2 = VoxelCSG::new(4);
+        csg2.fill_cube(IVec3::new(1,1,0), IVec3::new(3,2,2));
+
+        csg1.union_with(&csg2);
+        assert_eq!(count_filled_voxels(&csg1), 10);
+        assert!(csg1.get_voxel(IVec3::new(2,1,1)));
+        assert!(csg1.get_voxel(IVec3::new(0,0,0)));
+    }
+
+    #[test]
+    fn test_intersect_with() {
+        let mut csg1 = VoxelCSG::new(4);
+        csg1.fill_cube(IVec3::new(0,0,0), IVec3::new(2,2,2));
+        let mut csg2 = VoxelCSG::new(4);
+        csg2.fill_cube(IVec3::new(1,1,0), IVec3::new(3,2,2));
+
+        csg1.intersect_with(&csg2);
+        assert_eq!(count_filled_voxels(&csg1), 2);
+        assert!(csg1.get_voxel(IVec3::new(1,1,0)));
+        assert!(!csg1.get_voxel(IVec3::new(0,0,0)));
+    }
+
+    #[test]
+    fn test_subtract() {
+        let mut csg1 = VoxelCSG::new(4);
+        csg1.fill_cube(IVec3::new(0,0,0), IVec3::new(3,3,3));
+        let mut csg2 = VoxelCSG::new(4);
+        csg2.fill_cube(IVec3::new(1,1,0), IVec3::new(3,3,3));
+
+        csg1.subtract(&csg2);
+        assert_eq!(count_filled_voxels(&csg1), 15);
+        assert!(csg1.get_voxel(IVec3::new(0,0,0)));
+        assert!(!csg1.get_voxel(IVec3::new(1,1,1)));
+
+        // Cleared voxels can be filled again.
+        csg1.union_with(&csg2);
+        assert_eq!(count_filled_voxels(&csg1), 27);
+    }
+
+    #[test]
+    fn test_crop() {
+        let mut csg = VoxelCSG::new(5);
+        csg.fill_sphere(IVec3::new(0,0,0), 4.0);
+
+        // Keep the upper half (z >= 1).
+        let upper = csg.crop(IVec3::new(-4,-4,1), IVec3::new(4,4,4));
+        assert!(upper.get_voxel(IVec3::new(0,0,1)));
+        assert!(upper.get_voxel(IVec3::new(0,0,4)));
+        assert!(!upper.get_voxel(IVec3::new(0,0,0)));
+        assert!(!upper.get_voxel(IVec3::new(0,0,-3)));
+
+        let expected = csg.iter_filled().filter(|p| p.z >= 1).count();
+        assert_eq!(count_filled_voxels(&upper), expected);
+    }
+
+    // ------------------------------------------------------------
+    // 12) Accessors
+    // ------------------------------------------------------------
+    #[test]
+    fn test_height_and_node_count() {
+        let mut csg = VoxelCSG::new(4);
+        assert_eq!(csg.height(), 4);
+        assert_eq!(csg.node_count(), 0);
+
+        csg.fill_cube(IVec3::new(0,0,0), IVec3::new(1,1,1));
+        // One leaf plus its three ancestors.
+        assert_eq!(csg.node_count(), 4);
+
+        let before = csg.node_count();
+        csg.fill_cube(IVec3::new(0,0,0), IVec3::new(4,4,4));
+        assert!(csg.node_count() > before);
+    }
+
+    #[test]
+    fn test_filled_accessors_agree() {
+        let mut csg = VoxelCSG::new(5);
+        csg.fill_sphere(IVec3::new(0,0,0), 3.0);
+        csg.fill_cube(IVec3::new(-6,-6,-6), IVec3::new(-4,-4,-4));
+
+        let filled: std::collections::HashSet<IVec3> = csg.iter_filled().collect();
+        // No duplicates, and the count matches.
+        assert_eq!(filled.len(), csg.count_filled());
+        // Every iterated coordinate is reported as filled by `get_voxel`,
+        // and nothing else in the region is.
+        assert_voxels_match(&csg, &filled, IVec3::new(-7,-7,-7), IVec3::new(4,4,4));
+    }
+
+    #[test]
+    fn test_difference_keeping_height() {
+        let mut a = VoxelCSG::new(4);
+        a.fill_cube(IVec3::new(0,0,0), IVec3::new(3,3,3));
+        let mut b = VoxelCSG::new(6);
+        b.fill_cube(IVec3::new(1,1,0), IVec3::new(3,3,3));
+
+        let kept = a.difference_keeping_height(&b);
+        assert_eq!(kept.height(), a.height());
+
+        let regular = a.difference(&b);
+        assert_eq!(regular.height(), b.height());
+
+        let expected: std::collections::HashSet<IVec3> = regular.iter_filled().collect();
+        assert_eq!(count_filled_voxels(&kept), 15);
+        assert_voxels_match(&kept, &expected, IVec3::new(-1,-1,-1), IVec3::new(4,4,4));
+    }
+
+    // ------------------------------------------------------------
+    // 13) Read-only views
+    // ------------------------------------------------------------
+    #[test]
+    fn test_view_concurrent_queries() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<crate::VoxelCSGView<'static>>();
+
+        let mut csg = VoxelCSG::new(5);
+        csg.fill_sphere(IVec3::new(0,0,0), 4.0);
+        let view = csg.as_view();
+        assert_eq!(view.bounding_box(), Some((IVec3::new(-4,-4,-4), IVec3::new(4,4,4))));
+
+        let expected: Vec<bool> = (-6..=6).map(|x| csg.get_voxel(IVec3::new(x, 1, 0))).collect();
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| {
+                    scope.spawn(move || {
+                        let probes: Vec<bool> = (-6..=6).map(|x| view.get_voxel(IVec3::new(x, 1, 0))).collect();
+                        (probes, view.iter_filled().count())
+                    })
+                })
+                .collect();
+            for handle in handles {
+                let (probes, filled) = handle.join().unwrap();
+                assert_eq!(probes, expected);
+                assert_eq!(filled, csg.count_filled());
+            }
+        });
+    }
+
+    // ------------------------------------------------------------
+    // 14) Bulk construction
+    // ------------------------------------------------------------
+    #[test]
+    fn test_from_coords() {
+        let coords = [
+            IVec3::new(0,0,0),
+            IVec3::new(3,-2,1),
+            IVec3::new(-9,4,7),
+            IVec3::new(20,0,-1),
+        ];
+        let csg = VoxelCSG::from_coords(coords);
+
+        assert_eq!(count_filled_voxels(&csg), coords.len());
+        for c in coords {
+            assert!(csg.get_voxel(c), "{:?} should be filled", c);
+        }
+        // A root spans 2^(height-1) voxels, which must cover |20|.
+        assert!(1 << (csg.height() - 1) > 20);
+        assert_eq!(csg.height(), 6);
+
+        let empty = VoxelCSG::from_coords(Vec::new());
+        assert_eq!(empty.height(), 2);
+        assert_eq!(count_filled_voxels(&empty), 0);
+
+        // The most negative coordinate needs the full height, without overflow.
+        let corner = VoxelCSG::from_coords([IVec3::new(i32::MIN, 0, 5)]);
+        assert_eq!(corner.height(), 32);
+        assert!(corner.get_voxel(IVec3::new(i32::MIN, 0, 5)));
+    }
+
+    #[test]
+    fn test_new_with_hint() {
+        // The hint is ignored for now, so hinted and plain builds match.
+        let mut hinted = VoxelCSG::new_with_hint(6, 10_000);
+        let mut plain = VoxelCSG::new(6);
+        for csg in [&mut hinted, &mut plain] {
+            csg.fill_sphere(IVec3::new(5,5,5), 6.0);
+            csg.fill_cube(IVec3::new(-3,0,0), IVec3::new(3,3,3));
+        }
+        assert_eq!(hinted.height(), plain.height());
+        assert_eq!(hinted, plain);
+    }
+
+    #[test]
+    fn test_fill_sphere_supersampled() {
+        let center = IVec3::new(0,0,0);
+        let mut plain = VoxelCSG::new(6);
+        plain.fill_sphere(center, 10.0);
+
+        // One sample per voxel is the plain center test.
+        let mut one = VoxelCSG::new(6);
+        one.fill_sphere_supersampled(center, 10.0, 1);
+        assert_eq!(count_filled_voxels(&one), count_filled_voxels(&plain));
+
+        // With 3^3 samples the count stays within 2% of 4/3 * pi * r^3.
+        let mut three = VoxelCSG::new(6);
+        three.fill_sphere_supersampled(center, 10.0, 3);
+        let analytic = 4.0 / 3.0 * std::f32::consts::PI * 1000.0;
+        let count = count_filled_voxels(&three) as f32;
+        assert!((count - analytic).abs() / analytic < 0.02, "count {} vs {}", count, analytic);
+        assert!(three.get_voxel(IVec3::new(0,0,0)));
+        assert!(!three.get_voxel(IVec3::new(11,0,0)));
+    }
+
+    #[test]
+    fn test_fill_cylinder_supersampled() {
+        let mut plain = VoxelCSG::new(5);
+        plain.fill_cylinder(IVec3::new(0,0,0), 3, 4.0);
+        let mut one = VoxelCSG::new(5);
+        one.fill_cylinder_supersampled(IVec3::new(0,0,0), 3, 4.0, 1);
+        assert_eq!(count_filled_voxels(&one), count_filled_voxels(&plain));
+
+        let mut three = VoxelCSG::new(5);
+        three.fill_cylinder_supersampled(IVec3::new(0,0,0), 3, 4.0, 3);
+        assert!(three.get_voxel(IVec3::new(0,0,2)));
+        assert!(!three.get_voxel(IVec3::new(0,0,3)));
+        assert!(!three.get_voxel(IVec3::new(5,0,0)));
+    }
+
+    // ------------------------------------------------------------
+    // 15) Morton codes
+    // ------------------------------------------------------------
+    #[test]
+    fn test_to_morton_codes() {
+        let mut csg = VoxelCSG::new(4);
+        csg.fill_cube(IVec3::new(4,2,3), IVec3::new(6,3,4)); // (4,2,3) and (5,2,3)
+
+        let codes = csg.to_morton_codes();
+        assert_eq!(codes.len(), 2);
+        assert!(codes[0] < codes[1]);
+        // Adjacent voxels share all but their lowest few interleaved bits.
+        assert!(codes[1] - codes[0] < 64, "codes too far apart: {:?}", codes);
+
+        // Decoding restores the original coordinates.
+        let decoded: std::collections::HashSet<IVec3> =
+            codes.iter().map(|&c| crate::morton_decode(c)).collect();
+        let expected: std::collections::HashSet<IVec3> = csg.iter_filled().collect();
+        assert_eq!(decoded, expected);
+
+        let far = IVec3::new(-1_000_000, 12345, 1_048_575);
+        assert_eq!(crate::morton_decode(crate::morton_encode(far)), far);
+    }
+
+    #[test]
+    fn test_from_morton_codes_round_trip() {
+        let mut csg = VoxelCSG::new(5);
+        csg.fill_sphere(IVec3::new(-3,2,1), 3.0);
+        csg.fill_cube(IVec3::new(5,5,5), IVec3::new(8,6,7));
+
+        let codes = csg.to_morton_codes();
+        let decoded = VoxelCSG::from_morton_codes(&codes, crate::MORTON_OFFSET);
+        assert_eq!(decoded, csg);
+
+        // Structural equality ignores height but not content.
+        let mut other = VoxelCSG::new(6);
+        other.union_with(&csg);
+        assert_eq!(other, csg);
+        other.fill_cube(IVec3::new(20,20,20), IVec3::new(21,21,21));
+        assert_ne!(other, csg);
+    }
+
+    // ------------------------------------------------------------
+    // 16) Image-stack export
+    // ------------------------------------------------------------
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_save_slices_as_png() {
+        let mut csg = VoxelCSG::new(4);
+        csg.fill_cube(IVec3::new(1,2,-1), IVec3::new(4,4,2)); // 3 x 2 x 3
+
+        let dir = std::env::temp_dir().join("voxelcsgrs_test_slices");
+        std::fs::remove_dir_all(&dir).ok();
+        csg.save_slices_as_png(dir.to_str().unwrap()).expect("Saving slices failed");
+
+        let files = std::fs::read_dir(&dir).unwrap().count();
+        assert_eq!(files, 3);
+        for z in -1..2 {
+            let layer = image::open(dir.join(format!("layer_{}.png", z))).unwrap().to_luma8();
+            assert_eq!(layer.dimensions(), (3, 2));
+            assert!(layer.pixels().all(|p| p.0[0] == 255));
+        }
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_fill_capsule() {
+        let mut csg = VoxelCSG::new(5);
+        csg.fill_capsule(IVec3::new(0,0,0), IVec3::new(6,0,0), 2.0);
+
+        // Along the axis and the rounded caps:
+        assert!(csg.get_voxel(IVec3::new(3,0,0)));
+        assert!(csg.get_voxel(IVec3::new(-2,0,0)));
+        assert!(csg.get_voxel(IVec3::new(8,0,0)));
+        assert!(csg.get_voxel(IVec3::new(3,2,0)));
+        // Outside the radius, and corners the round caps shave off:
+        assert!(!csg.get_voxel(IVec3::new(3,3,0)));
+        assert!(!csg.get_voxel(IVec3::new(-2,1,0)));
+    }
+
+    #[test]
+    fn test_fill_sweep() {
+        let mut csg = VoxelCSG::new(5);
+        let path = [IVec3::new(0,0,0), IVec3::new(6,0,0), IVec3::new(6,6,0)];
+        csg.fill_sweep(&path, 1.5);
+
+        // Corner and both end caps:
+        assert!(csg.get_voxel(IVec3::new(6,0,0)));
+        assert!(csg.get_voxel(IVec3::new(-1,0,0)));
+        assert!(csg.get_voxel(IVec3::new(6,7,0)));
+        // No gap anywhere along either leg, including through the bend:
+        for x in 0..=6 {
+            assert!(csg.get_voxel(IVec3::new(x,0,0)), "gap at x={}", x);
+        }
+        for y in 0..=6 {
+            assert!(csg.get_voxel(IVec3::new(6,y,0)), "gap at y={}", y);
+        }
+        assert!(!csg.get_voxel(IVec3::new(3,3,0)));
+
+        // A single point is a sphere.
+        let mut single = VoxelCSG::new(5);
+        single.fill_sweep(&[IVec3::new(1,1,1)], 2.0);
+        let mut sphere = VoxelCSG::new(5);
+        sphere.fill_sphere(IVec3::new(1,1,1), 2.0);
+        assert_eq!(single, sphere);
+    }
+
+    #[test]
+    fn test_fill_revolve() {
+        use crate::Axis;
+        use std::num::NonZeroU32;
+
+        // A 3 x 4 rectangle touching the axis revolves into a solid cylinder
+        // of radius 3 spanning 5 voxel layers (h = 0..=4).
+        let profile = [(0, 0), (3, 0), (3, 4), (0, 4)];
+        let mut revolved = VoxelCSG::new(5);
+        revolved.fill_revolve(&profile, Axis::Z, IVec3::new(1,2,3), None);
+
+        let mut cylinder = VoxelCSG::new(5);
+        cylinder.fill_cylinder(IVec3::new(1,2,3), 5, 3.0);
+        assert_eq!(revolved, cylinder);
+
+        // Around X, the same profile extends along +X instead.
+        let mut around_x = VoxelCSG::new(5);
+        around_x.fill_revolve(&profile, Axis::X, IVec3::new(0,0,0), None);
+        assert!(around_x.get_voxel(IVec3::new(4,0,3)));
+        assert!(!around_x.get_voxel(IVec3::new(5,0,0)));
+        assert!(!around_x.get_voxel(IVec3::new(0,3,3)));
+
+        // Four facets turn the cylinder into a square prism whose corners
+        // touch the profile radius.
+        let mut square = VoxelCSG::new(5);
+        square.fill_revolve(&[(0, 0), (4, 0), (4, 1), (0, 1)], Axis::Z, IVec3::new(0,0,0), NonZeroU32::new(4));
+        assert!(square.get_voxel(IVec3::new(2,2,0)));
+        assert!(!square.get_voxel(IVec3::new(3,2,0)));
+    }
+
+    #[test]
+    #[should_panic(expected = "at least 3 steps")]
+    fn test_fill_revolve_rejects_degenerate_steps() {
+        use crate::Axis;
+        use std::num::NonZeroU32;
+
+        let mut csg = VoxelCSG::new(5);
+        csg.fill_revolve(&[(0, 0), (4, 0), (4, 1), (0, 1)], Axis::Z, IVec3::new(0,0,0), NonZeroU32::new(2));
+    }
+
+    // ------------------------------------------------------------
+    // 17) Generic values
+    // ------------------------------------------------------------
+    #[test]
+    fn test_map_values() {
+        let mut materials: VoxelCSG<u8> = VoxelCSG::with_height(5);
+        materials.set(IVec3::new(0,0,0), 1);
+        materials.set(IVec3::new(1,2,3), 7);
+        materials.set(IVec3::new(-4,0,2), 0);
+
+        let bumped = materials.map_values(|_, v| v + 1);
+        assert_eq!(bumped.get(IVec3::new(0,0,0)), Some(&2));
+        assert_eq!(bumped.get(IVec3::new(1,2,3)), Some(&8));
+        // The stored-but-empty 0 becomes a filled 1.
+        assert_eq!(bumped.get(IVec3::new(-4,0,2)), Some(&1));
+        assert_eq!(bumped.get(IVec3::new(5,5,5)), None);
+        assert_eq!(bumped.count_filled(), 3);
+        assert_eq!(materials.count_filled(), 2);
+
+        // Position-dependent recoloring.
+        let by_x = materials.map_values(|coords, v| if coords.x > 0 { 9 } else { *v });
+        assert_eq!(by_x.get(IVec3::new(1,2,3)), Some(&9));
+        assert_eq!(by_x.get(IVec3::new(0,0,0)), Some(&1));
+    }
+
+    #[test]
+    fn test_fill_cube_value() {
+        let mut colors: VoxelCSG<u8> = VoxelCSG::with_height(5);
+        colors.fill_cube_value(IVec3::new(0,0,0), IVec3::new(8,8,8), 1);
+        colors.fill_cube_value(IVec3::new(4,4,4), IVec3::new(12,6,6), 2);
+
+        assert_eq!(colors.get(IVec3::new(0,0,0)), Some(&1));
+        assert_eq!(colors.get(IVec3::new(7,7,7)), Some(&1));
+        assert_eq!(colors.get(IVec3::new(3,4,4)), Some(&1));
+        assert_eq!(colors.get(IVec3::new(4,4,4)), Some(&2));
+        assert_eq!(colors.get(IVec3::new(11,5,5)), Some(&2));
+        assert_eq!(colors.get(IVec3::new(12,5,5)), None);
+        assert_eq!(colors.count_filled(), 512 + 4 * 2 * 2);
+
+        // Writing 0 (empty) clears.
+        colors.fill_cube_value(IVec3::new(0,0,0), IVec3::new(2,2,2), 0);
+        assert_eq!(colors.get(IVec3::new(1,1,1)), None);
+        assert_eq!(colors.count_filled(), 512 + 16 - 8);
+    }
+
+    #[test]
+    fn test_merge_with() {
+        let mut low: VoxelCSG<u8> = VoxelCSG::with_height(5);
+        low.fill_cube_value(IVec3::new(0,0,0), IVec3::new(4,4,4), 3);
+        let mut high: VoxelCSG<u8> = VoxelCSG::with_height(5);
+        high.fill_cube_value(IVec3::new(2,0,0), IVec3::new(6,4,4), 7);
+        high.set(IVec3::new(2,0,0), 1);
+
+        let max = low.merge_with(&high, |a, b| a.max(b).copied());
+        assert_eq!(max.get(IVec3::new(0,0,0)), Some(&3));
+        assert_eq!(max.get(IVec3::new(3,1,1)), Some(&7));
+        assert_eq!(max.get(IVec3::new(2,0,0)), Some(&3));
+        assert_eq!(max.get(IVec3::new(5,3,3)), Some(&7));
+        assert_eq!(max.count_filled(), 6 * 4 * 4);
+
+        // Intersection as a special case.
+        let both = low.merge_with(&high, |a, b| a.and(b).copied());
+        assert_eq!(both.count_filled(), 2 * 4 * 4);
+        assert_eq!(both.get(IVec3::new(3,0,0)), Some(&7));
+    }
+
+    #[test]
+    fn test_generic_intersection_difference() {
+        let mut a = VoxelCSG::<u8>::with_height(4);
+        a.set(IVec3::new(0,0,0), 7);
+        a.set(IVec3::new(1,0,0), 9);
+        a.set(IVec3::new(2,0,0), 11);
+        let mut b = VoxelCSG::<u8>::with_height(4);
+        b.set(IVec3::new(1,0,0), 200);
+        b.set(IVec3::new(2,0,0), 201);
+        b.set(IVec3::new(3,0,0), 202);
+
+        let both = a.intersection(&b);
+        assert_eq!(both.count_filled(), 2);
+        assert_eq!(both.get(IVec3::new(1,0,0)), Some(&9));
+        assert_eq!(both.get(IVec3::new(2,0,0)), Some(&11));
+
+        let only_a = a.difference(&b);
+        assert_eq!(only_a.count_filled(), 1);
+        assert_eq!(only_a.get(IVec3::new(0,0,0)), Some(&7));
+        assert_eq!(only_a.get(IVec3::new(3,0,0)), None);
+
+        // Values under summarizing nodes survive too.
+        let mut block = VoxelCSG::<u8>::with_height(4);
+        block.fill_cube_value(IVec3::ZERO, IVec3::splat(4), 5);
+        assert_eq!(block.intersection(&a).get(IVec3::new(2,0,0)), Some(&5));
+        assert_eq!(block.difference(&a).get(IVec3::new(3,3,3)), Some(&5));
+        assert_eq!(block.difference(&a).count_filled(), 64 - 3);
+    }
+
+    #[test]
+    fn test_build_lookup() {
+        let mut csg = VoxelCSG::new(5);
+        csg.fill_sphere(IVec3::new(1,2,3), 4.0);
+        csg.fill_cube(IVec3::new(-8,-8,-8), IVec3::new(-4,-6,-5));
+        let lookup = csg.build_lookup();
+        assert_eq!(lookup.len(), csg.count_filled());
+
+        for z in -9..9 {
+            for y in -9..9 {
+                for x in -9..9 {
+                    let p = IVec3::new(x, y, z);
+                    assert_eq!(lookup.contains(p), csg.get_voxel(p), "{:?}", p);
+                }
+            }
+        }
+
+        // A snapshot: later edits are not seen.
+        csg.set_voxel(IVec3::new(8,8,8), true);
+        assert!(!lookup.contains(IVec3::new(8,8,8)));
+        assert!(VoxelCSG::new(3).build_lookup().is_empty());
+    }
+
+    #[test]
+    fn test_union_tagged() {
+        let mut a = VoxelCSG::new(4);
+        a.fill_cube(IVec3::new(0,0,0), IVec3::new(4,4,4));
+        let mut b = VoxelCSG::new(4);
+        b.fill_cube(IVec3::new(2,0,0), IVec3::new(6,4,4));
+
+        let tagged = a.union_tagged(&b);
+        assert_eq!(tagged.count_filled(), a.union(&b).count_filled());
+        assert_eq!(tagged.get(IVec3::new(0,1,1)), Some(&1));
+        assert_eq!(tagged.get(IVec3::new(2,1,1)), Some(&3));
+        assert_eq!(tagged.get(IVec3::new(3,3,3)), Some(&3));
+        assert_eq!(tagged.get(IVec3::new(5,1,1)), Some(&2));
+        assert_eq!(tagged.get(IVec3::new(7,1,1)), None);
+        assert_eq!(tagged.iter_filled().filter(|&p| tagged.get(p) == Some(&3)).count(), 32);
+    }
+
+    #[test]
+    fn test_downsample_avg() {
+        let mut field = VoxelCSG::<f32>::with_height(4);
+        // Half of the 2x2x2 block at the origin, with varying densities.
+        field.set(IVec3::new(0,0,0), 1.0);
+        field.set(IVec3::new(1,0,0), 0.5);
+        field.set(IVec3::new(0,1,0), 0.25);
+        field.set(IVec3::new(1,1,0), 0.25);
+        // A lone voxel in the block at (-1, 0, 0).
+        field.set(IVec3::new(-1,1,1), 0.8);
+
+        let mip = field.downsample_avg(2);
+        assert_eq!(mip.count_filled(), 2);
+        assert_eq!(mip.get(IVec3::ZERO), Some(&(2.0 / 8.0)));
+        assert_eq!(mip.get(IVec3::new(-1,0,0)), Some(&(0.8 / 8.0)));
+        assert_eq!(field.downsample_avg(1).get(IVec3::new(1,0,0)), Some(&0.5));
+    }
+
+    // ------------------------------------------------------------
+    // 18) Box fills
+    // ------------------------------------------------------------
+    #[test]
+    fn test_fill_box() {
+        use grid_tree::glam::UVec3;
+
+        let origin = IVec3::new(-1,2,-3);
+        let size = UVec3::new(2,3,4);
+        let mut csg = VoxelCSG::new(5);
+        csg.fill_box(origin, size);
+        assert_eq!(csg.count_filled(), 24);
+        assert!(csg.get_voxel(origin));
+        assert!(csg.get_voxel(origin + size.as_ivec3() - IVec3::ONE));
+        assert!(!csg.get_voxel(origin + size.as_ivec3()));
+
+        let mut empty = VoxelCSG::new(5);
+        empty.fill_box(origin, UVec3::new(2,0,4));
+        assert_eq!(empty.count_filled(), 0);
+    }
+
+    #[test]
+    fn test_fill_cube_diff() {
+        let mut csg = VoxelCSG::new(5);
+        let first = csg.fill_cube_diff(IVec3::new(0,0,0), IVec3::new(2,2,2));
+        assert_eq!(first.len(), 8);
+
+        // Overlaps the first cube on x = 1 only.
+        let second = csg.fill_cube_diff(IVec3::new(1,0,0), IVec3::new(3,2,2));
+        assert_eq!(second.len(), 4);
+        assert!(second.iter().all(|c| c.x == 2));
+        assert_eq!(csg.count_filled(), 12);
+
+        assert!(csg.fill_cube_diff(IVec3::new(0,0,0), IVec3::new(3,2,2)).is_empty());
+    }
+
+    #[test]
+    fn test_try_fill_cube() {
+        use crate::InvalidBounds;
+
+        let mut csg = VoxelCSG::new(4);
+        let (min, max) = (IVec3::new(0,3,0), IVec3::new(2,1,2));
+        assert_eq!(csg.try_fill_cube(min, max), Err(InvalidBounds { min, max }));
+        assert_eq!(csg.count_filled(), 0);
+
+        assert_eq!(csg.try_fill_cube(IVec3::new(1,1,1), IVec3::new(1,1,1)), Ok(()));
+        assert_eq!(csg.try_fill_cube(IVec3::new(0,0,0), IVec3::new(2,2,2)), Ok(()));
+        assert_eq!(csg.count_filled(), 8);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "reversed bounds")]
+    fn test_fill_cube_reversed_bounds_debug_asserts() {
+        VoxelCSG::new(4).fill_cube(IVec3::new(0,3,0), IVec3::new(2,1,2));
+    }
+
+    #[test]
+    fn test_fill_cube_clamped() {
+        // Height 4 holds [-8, 7] on every axis.
+        let mut csg = VoxelCSG::new(4);
+        let region = csg.fill_cube_clamped(IVec3::new(4,-12,0), IVec3::new(12,-4,2));
+        assert_eq!(region, Some((IVec3::new(4,-8,0), IVec3::new(8,-4,2))));
+        assert_eq!(csg.height(), 4);
+        assert_eq!(csg.count_filled(), 4 * 4 * 2);
+        assert!(csg.get_voxel(IVec3::new(7,-8,1)));
+        assert!(!csg.get_voxel(IVec3::new(8,-8,1)));
+
+        // Entirely out of range: nothing filled.
+        assert_eq!(csg.fill_cube_clamped(IVec3::new(20,0,0), IVec3::new(30,5,5)), None);
+        assert_eq!(csg.count_filled(), 32);
+        assert_eq!(csg.height(), 4);
+    }
+
+    #[test]
+    fn test_counted_fills() {
+        let mut csg = VoxelCSG::new(5);
+        assert_eq!(csg.fill_cube_counted(IVec3::new(0,0,0), IVec3::new(4,3,2)), 24);
+        assert_eq!(csg.fill_cube_counted(IVec3::new(0,0,0), IVec3::new(4,3,2)), 0);
+        assert_eq!(csg.fill_cube_counted(IVec3::new(3,0,0), IVec3::new(5,3,2)), 6);
+
+        let mut sphere = VoxelCSG::new(5);
+        let added = sphere.fill_sphere_counted(IVec3::new(0,0,0), 2.0);
+        assert_eq!(added, sphere.count_filled());
+        assert_eq!(added, 33);
+        assert_eq!(sphere.fill_sphere_counted(IVec3::new(0,0,0), 2.0), 0);
+
+        // Voxels filled elsewhere in the shape don't affect the count.
+        sphere.fill_cube(IVec3::new(10,10,10), IVec3::new(14,14,14));
+        let before = sphere.count_filled();
+        let added = sphere.fill_sphere_counted(IVec3::new(1,0,0), 2.0);
+        assert_eq!(added, sphere.count_filled() - before);
+        assert!(added > 0 && added < 33);
+        assert_eq!(sphere.fill_cube_counted(IVec3::new(12,12,12), IVec3::new(16,13,13)), 2);
+    }
+
+    #[test]
+    fn test_paint_cube() {
+        let mut csg = VoxelCSG::new(5);
+        csg.fill_cube(IVec3::new(0,0,0), IVec3::new(3,3,3));
+        csg.paint_cube(IVec3::new(1,1,1), IVec3::new(2,2,2), false);
+        assert!(!csg.get_voxel(IVec3::new(1,1,1)));
+        assert_eq!(csg.count_filled(), 26);
+
+        // Erasing a large solid block and painting part of it back.
+        let mut block = VoxelCSG::new(6);
+        block.fill_cube(IVec3::new(0,0,0), IVec3::new(16,16,16));
+        block.paint_cube(IVec3::new(0,0,0), IVec3::new(16,16,8), false);
+        assert_eq!(block.count_filled(), 16 * 16 * 8);
+        block.paint_cube(IVec3::new(0,0,0), IVec3::new(2,2,2), true);
+        assert_eq!(block.count_filled(), 16 * 16 * 8 + 8);
+        assert!(block.get_voxel(IVec3::new(1,1,1)));
+        assert!(!block.get_voxel(IVec3::new(3,3,3)));
+    }
+
+    #[test]
+    fn test_estimate_fill_cost() {
+        use crate::FillCost;
+
+        let (min, max) = (IVec3::new(-5,-3,0), IVec3::new(11,9,7));
+        let csg = VoxelCSG::new(4);
+        let cost = csg.estimate_cube_cost(min, max);
+        assert_eq!(csg.count_filled(), 0);
+
+        let mut filled = csg.clone();
+        filled.fill_cube(min, max);
+        assert_eq!(cost.voxels, filled.count_filled() as u64);
+        assert_eq!(cost.new_nodes, filled.node_count() as u64);
+        // Filling the same box again allocates nothing.
+        assert_eq!(filled.estimate_cube_cost(min, max).new_nodes, 0);
+        assert_eq!(csg.estimate_cube_cost(max, min).voxels, 0);
+        // 2^93 voxels saturate; the box is one whole root node.
+        let huge = csg.estimate_cube_cost(IVec3::splat(i32::MIN), IVec3::ZERO);
+        assert_eq!(huge, FillCost { voxels: u64::MAX, new_nodes: 1 });
+
+        let sphere_cost = csg.estimate_sphere_cost(IVec3::new(2,2,2), 4.0);
+        let mut sphere = csg.clone();
+        sphere.fill_sphere(IVec3::new(2,2,2), 4.0);
+        assert_eq!(sphere_cost.voxels, sphere.count_filled() as u64);
+        assert_eq!(sphere_cost.new_nodes, sphere.node_count() as u64);
+    }
+
+    #[test]
+    fn test_fill_cube_streaming() {
+        // A 200^3 fill only allocates nodes along the cube's boundary.
+        let mut csg = VoxelCSG::new(9);
+        csg.fill_cube(IVec3::new(-100,-100,-100), IVec3::new(100,100,100));
+        assert_eq!(csg.count_filled(), 200 * 200 * 200);
+        let surface = 6 * 200 * 200;
+        assert!(csg.node_count() < surface, "{} nodes", csg.node_count());
+        assert!(csg.get_voxel(IVec3::new(99,-100,0)));
+        assert!(!csg.get_voxel(IVec3::new(100,0,0)));
+    }
+
+    #[test]
+    fn test_summarized_fill_cube() {
+        // A 64^3 block aligned to a level-6 node is stored as one node.
+        let mut csg = VoxelCSG::new(8);
+        csg.fill_cube(IVec3::new(0,0,0), IVec3::new(64,64,64));
+        assert_eq!(csg.count_filled(), 64 * 64 * 64);
+        assert!(csg.node_count() < 10);
+        assert!(csg.get_voxel(IVec3::new(0,0,0)));
+        assert!(csg.get_voxel(IVec3::new(63,63,63)));
+        assert!(csg.get_voxel(IVec3::new(17,40,5)));
+        assert!(!csg.get_voxel(IVec3::new(64,0,0)));
+        assert!(!csg.get_voxel(IVec3::new(-1,0,0)));
+
+        // Clearing one voxel splits the summary down to that leaf only.
+        csg.set_voxel(IVec3::new(17,40,5), false);
+        assert!(!csg.get_voxel(IVec3::new(17,40,5)));
+        assert!(csg.get_voxel(IVec3::new(16,40,5)));
+        assert_eq!(csg.count_filled(), 64 * 64 * 64 - 1);
+        assert!(csg.node_count() < 100);
+
+        // The progress-reporting fill builds the same tree for unaligned boxes.
+        let (min, max) = (IVec3::new(-5,-3,1), IVec3::new(9,12,20));
+        let mut fast = VoxelCSG::new(5);
+        fast.fill_cube(min, max);
+        let mut slow = VoxelCSG::new(5);
+        slow.fill_cube_with_progress(min, max, |_| {});
+        assert_eq!(fast, slow);
+        assert_eq!(fast.node_count(), slow.node_count());
+
+        // Filling over existing leaves and summaries.
+        fast.fill_cube(IVec3::new(0,0,0), IVec3::new(16,16,16));
+        slow.fill_cube_with_progress(IVec3::new(0,0,0), IVec3::new(16,16,16), |_| {});
+        assert_eq!(fast, slow);
+        assert_eq!(fast.invert(), slow.invert());
+        assert_eq!(fast.intersection(&slow), slow);
+    }
+
+    #[test]
+    fn test_fill_box_walls() {
+        use crate::FaceMask;
+
+        let mut open_top = VoxelCSG::new(5);
+        open_top.fill_box_walls(IVec3::new(0,0,0), IVec3::new(6,6,6), 1, FaceMask::POS_Z);
+
+        // The top is open: only the side walls reach z = 5.
+        for y in 1..5 {
+            for x in 1..5 {
+                assert!(!open_top.get_voxel(IVec3::new(x,y,5)));
+                // The floor is closed.
+                assert!(open_top.get_voxel(IVec3::new(x,y,0)));
+            }
+        }
+        assert!(open_top.get_voxel(IVec3::new(0,3,5)));
+        assert!(open_top.get_voxel(IVec3::new(5,3,3)));
+        assert!(open_top.get_voxel(IVec3::new(3,0,3)));
+        assert!(open_top.get_voxel(IVec3::new(3,5,3)));
+        assert!(!open_top.get_voxel(IVec3::new(3,3,3)));
+        assert_eq!(open_top.count_filled(), 6 * 6 * 6 - 4 * 4 * 5);
+
+        // All faces closed with thick walls; a tube open at both X ends.
+        let mut closed = VoxelCSG::new(5);
+        closed.fill_box_walls(IVec3::new(0,0,0), IVec3::new(6,6,6), 2, FaceMask::NONE);
+        assert_eq!(closed.count_filled(), 6 * 6 * 6 - 2 * 2 * 2);
+        let mut tube = VoxelCSG::new(5);
+        tube.fill_box_walls(IVec3::new(0,0,0), IVec3::new(6,6,6), 1, FaceMask::NEG_X | FaceMask::POS_X);
+        assert_eq!(tube.count_filled(), 6 * (36 - 16));
+    }
+
+    #[test]
+    fn test_fill_box_shell() {
+        use crate::FaceMask;
+
+        let mut shell = VoxelCSG::new(5);
+        shell.fill_box_shell(IVec3::new(-3,0,2), IVec3::new(7,10,12), 1);
+        assert_eq!(shell.count_filled(), 1000 - 512);
+        assert!(shell.region_is_empty(IVec3::new(-2,1,3), IVec3::new(5,8,10)));
+        assert!(shell.get_voxel(IVec3::new(-3,5,5)));
+        assert!(shell.get_voxel(IVec3::new(6,9,11)));
+
+        // Same voxels as the per-voxel walls.
+        let mut walls = VoxelCSG::new(5);
+        walls.fill_box_walls(IVec3::new(-3,0,2), IVec3::new(7,10,12), 1, FaceMask::NONE);
+        assert_eq!(shell, walls);
+
+        // Thick enough to meet in the middle: a solid box.
+        let mut solid = VoxelCSG::new(5);
+        solid.fill_box_shell(IVec3::ZERO, IVec3::new(4,10,10), 2);
+        assert_eq!(solid.count_filled(), 400);
+    }
+
+    #[test]
+    fn test_fill_checkerboard() {
+        let mut csg = VoxelCSG::<u8>::with_height(4);
+        csg.fill_checkerboard(IVec3::new(-2,0,0), IVec3::new(2,3,2), 10, 20);
+        assert_eq!(csg.count_filled(), 4 * 3 * 2);
+        assert_eq!(csg.get(IVec3::new(0,0,0)), Some(&10));
+        assert_eq!(csg.get(IVec3::new(1,0,0)), Some(&20));
+        assert_eq!(csg.get(IVec3::new(-1,0,0)), Some(&20));
+        assert_eq!(csg.get(IVec3::new(-2,1,1)), Some(&10));
+        for p in csg.iter_filled() {
+            let neighbor = p + IVec3::X;
+            if let Some(other) = csg.get(neighbor) {
+                assert_ne!(csg.get(p), Some(other));
+            }
+        }
+    }
+
+    #[test]
+    fn test_fill_lattice() {
+        let mut csg = VoxelCSG::new(5);
+        csg.fill_lattice(IVec3::new(1,1,1), IVec3::new(5,5,5), IVec3::new(2,2,2));
+        assert_eq!(csg.count_filled(), 8);
+        for z in [1, 3] {
+            for y in [1, 3] {
+                for x in [1, 3] {
+                    assert!(csg.get_voxel(IVec3::new(x,y,z)));
+                }
+            }
+        }
+        assert!(!csg.get_voxel(IVec3::new(2,1,1)));
+
+        // Stride 1 on X and Y: every third Z plane.
+        let mut planes = VoxelCSG::new(5);
+        planes.fill_lattice(IVec3::new(0,0,0), IVec3::new(4,4,7), IVec3::new(1,1,3));
+        assert_eq!(planes.count_filled(), 3 * 16);
+    }
+
+    // ------------------------------------------------------------
+    // 19) More primitives
+    // ------------------------------------------------------------
+    #[test]
+    fn test_fill_disc() {
+        use crate::Axis;
+
+        let mut csg = VoxelCSG::new(5);
+        csg.fill_disc(IVec3::new(0,0,5), 3.0, Axis::Z);
+
+        assert!(csg.iter_filled().all(|c| c.z == 5));
+        assert!(csg.get_voxel(IVec3::new(0,0,5)));
+        assert!(csg.get_voxel(IVec3::new(3,0,5)));
+        assert!(csg.get_voxel(IVec3::new(0,-3,5)));
+        assert!(csg.get_voxel(IVec3::new(2,2,5)));
+        assert!(!csg.get_voxel(IVec3::new(3,1,5)));
+        assert!(!csg.get_voxel(IVec3::new(4,0,5)));
+        assert_eq!(csg.count_filled(), 29);
+
+        // Same as a one-layer cylinder.
+        let mut cylinder = VoxelCSG::new(5);
+        cylinder.fill_cylinder(IVec3::new(0,0,5), 1, 3.0);
+        assert_eq!(csg, cylinder);
+    }
+
+    #[test]
+    fn test_fill_pyramid() {
+        use crate::Axis;
+
+        let mut csg = VoxelCSG::new(5);
+        csg.fill_pyramid(IVec3::new(0,0,0), IVec3::new(6,4,0), 4, Axis::Z);
+
+        let layer = |z: i32| csg.iter_filled().filter(|c| c.z == z).count();
+        assert_eq!(layer(0), 7 * 5);
+        assert_eq!(layer(4), 1);
+        assert!(csg.get_voxel(IVec3::new(3,2,4)));
+        for z in 0..4 {
+            assert!(layer(z + 1) <= layer(z));
+        }
+        assert_eq!(layer(5), 0);
+
+        // An even side ends in a two-voxel apex; negative heights point down.
+        let mut even = VoxelCSG::new(5);
+        even.fill_pyramid(IVec3::new(0,0,0), IVec3::new(3,2,0), -2, Axis::Z);
+        assert_eq!(even.iter_filled().filter(|c| c.z == -2).count(), 2);
+        assert!(even.get_voxel(IVec3::new(1,1,-2)));
+        assert!(even.get_voxel(IVec3::new(2,1,-2)));
+    }
+
+    #[test]
+    fn test_fill_density() {
+        let mut csg = VoxelCSG::new(5);
+        csg.fill_density(IVec3::new(0,0,0), IVec3::new(3,3,3), |p| (p.x + p.y + p.z) as f32, 8.0);
+
+        // Sums 8 and 9 only occur in the far corner of the 4^3 region.
+        assert!(csg.iter_filled().all(|p| p.x + p.y + p.z >= 8));
+        assert!(csg.get_voxel(IVec3::new(3,3,3)));
+        assert!(csg.get_voxel(IVec3::new(2,3,3)));
+        assert!(!csg.get_voxel(IVec3::new(2,2,3)));
+        assert!(!csg.get_voxel(IVec3::new(0,0,0)));
+        assert_eq!(csg.count_filled(), 4);
+    }
+
+    #[test]
+    fn test_stamp_bitmap() {
+        use crate::Axis;
+
+        let plus = [
+            false, true, false,
+            true,  true, true,
+            false, true, false,
+        ];
+        let origin = IVec3::new(1,2,3);
+        let mut csg = VoxelCSG::new(4);
+        csg.stamp_bitmap(origin, &plus, 3, 2, Axis::Z);
+        assert_eq!(csg.count_filled(), 5 * 2);
+        for z in 0..2 {
+            for (i, &pixel) in plus.iter().enumerate() {
+                let p = origin + IVec3::new((i % 3) as i32, (i / 3) as i32, z);
+                assert_eq!(csg.get_voxel(p), pixel, "{:?}", p);
+            }
+        }
+        assert!(!csg.get_voxel(origin + IVec3::new(1,1,2)));
+
+        // Negative depth extrudes backwards along the axis.
+        let mut back = VoxelCSG::new(4);
+        back.stamp_bitmap(IVec3::ZERO, &plus, 3, -2, Axis::X);
+        assert_eq!(back.count_filled(), 10);
+        assert!(back.get_voxel(IVec3::new(-1,1,1)));
+        assert!(!back.get_voxel(IVec3::new(1,1,1)));
+    }
+
+    #[test]
+    fn test_fill_sphere_soft() {
+        let mut csg = VoxelCSG::<f32>::with_height(4);
+        csg.fill_sphere_soft(IVec3::ZERO, 3.0);
+
+        assert_eq!(csg.get(IVec3::ZERO), Some(&1.0));
+        assert_eq!(csg.get(IVec3::new(2,0,0)), Some(&1.0));
+        // On the surface: half covered.
+        let boundary = *csg.get(IVec3::new(3,0,0)).unwrap();
+        assert!(boundary > 0.0 && boundary < 1.0);
+        assert!((boundary - 0.5).abs() < 1e-6);
+        let diagonal = *csg.get(IVec3::new(2,2,1)).unwrap();
+        assert!(diagonal > 0.0 && diagonal < 1.0);
+        // Outside the band: unset.
+        assert_eq!(csg.get(IVec3::new(4,0,0)), None);
+        assert_eq!(csg.get(IVec3::new(3,3,0)), None);
+
+        // Overlaps keep the larger coverage.
+        csg.fill_sphere_soft(IVec3::new(6,0,0), 3.0);
+        assert_eq!(csg.get(IVec3::new(3,0,0)), Some(&0.5));
+        assert_eq!(csg.get(IVec3::new(2,0,0)), Some(&1.0));
+    }
+
+    #[test]
+    fn test_fill_sweep_tube() {
+        let mut csg = VoxelCSG::new(5);
+        csg.fill_sweep_tube(&[IVec3::new(0,0,0), IVec3::new(0,0,10)], 4.0, 2.0);
+
+        // A cross-section is a ring around an empty bore.
+        for z in 0..=10 {
+            assert!(!csg.get_voxel(IVec3::new(0,0,z)));
+            assert!(!csg.get_voxel(IVec3::new(2,0,z)));
+            assert!(csg.get_voxel(IVec3::new(3,0,z)));
+            assert!(csg.get_voxel(IVec3::new(0,-4,z)));
+            assert!(!csg.get_voxel(IVec3::new(0,5,z)));
+        }
+        // Open ends.
+        assert!(!csg.get_voxel(IVec3::new(3,0,-1)));
+        assert!(!csg.get_voxel(IVec3::new(3,0,11)));
+
+        // The bore stays clear through a right-angle bend.
+        let mut bent = VoxelCSG::new(5);
+        let path = [IVec3::new(0,0,0), IVec3::new(0,0,10), IVec3::new(10,0,10)];
+        bent.fill_sweep_tube(&path, 4.0, 2.0);
+        for p in [IVec3::new(0,0,10), IVec3::new(1,0,9), IVec3::new(1,1,10), IVec3::new(5,0,10)] {
+            assert!(!bent.get_voxel(p), "{:?}", p);
+        }
+        assert!(bent.get_voxel(IVec3::new(5,0,13)));
+        assert!(bent.get_voxel(IVec3::new(-3,0,10)));
+    }
+
+    #[test]
+    fn test_fill_skeleton() {
+        use crate::Connectivity;
+
+        // Shoulder, elbow, wrist.
+        let joints = [IVec3::new(0,0,0), IVec3::new(8,0,0), IVec3::new(8,6,0)];
+        let mut arm = VoxelCSG::new(5);
+        arm.fill_skeleton(&joints, &[(0, 1), (1, 2)], 1.5);
+
+        // Continuous from shoulder to wrist through the elbow.
+        for x in 0..=8 {
+            assert!(arm.get_voxel(IVec3::new(x,0,0)));
+        }
+        for y in 0..=6 {
+            assert!(arm.get_voxel(IVec3::new(8,y,0)));
+        }
+        assert!(arm.get_voxel(IVec3::new(9,-1,0)));
+        assert_eq!(arm.connected_components(Connectivity::Face6).len(), 1);
+
+        let mut bones_only = VoxelCSG::new(5);
+        bones_only.fill_capsule(joints[0], joints[1], 1.5);
+        bones_only.fill_capsule(joints[1], joints[2], 1.5);
+        assert_eq!(arm, bones_only);
+    }
+
+    #[test]
+    fn test_fill_sphere_i() {
+        // Lattice points with x^2 + y^2 + z^2 <= r^2.
+        for (radius, expected) in [(0, 1), (1, 7), (2, 33), (3, 123), (4, 257), (5, 515)] {
+            let mut csg = VoxelCSG::new(5);
+            csg.fill_sphere_i(IVec3::new(3,-2,1), radius);
+            assert_eq!(csg.count_filled(), expected, "radius {}", radius);
+
+            let mut float = VoxelCSG::new(5);
+            float.fill_sphere(IVec3::new(3,-2,1), radius as f32);
+            assert_eq!(csg, float);
+        }
+
+        let mut csg = VoxelCSG::new(5);
+        csg.fill_sphere_i(IVec3::ZERO, -1);
+        assert_eq!(csg.count_filled(), 0);
+    }
+
+    #[test]
+    fn test_fill_obb() {
+        use grid_tree::glam::{Mat3, Vec3};
+
+        let mut tilted = VoxelCSG::new(5);
+        tilted.fill_obb(IVec3::ZERO, Vec3::new(6.0, 1.0, 1.0), Mat3::from_rotation_z(std::f32::consts::FRAC_PI_4));
+
+        // Along the diagonal, inside the tilted box.
+        assert!(tilted.get_voxel(IVec3::new(3,3,0)));
+        assert!(tilted.get_voxel(IVec3::new(-3,-3,1)));
+        // Corners of the unrotated box fall outside.
+        for corner in [IVec3::new(6,1,0), IVec3::new(6,-1,0), IVec3::new(-6,1,0), IVec3::new(-6,-1,0)] {
+            assert!(!tilted.get_voxel(corner), "{:?}", corner);
+        }
+        assert!(!tilted.get_voxel(IVec3::new(3,3,2)));
+
+        // Without rotation it is a plain box.
+        let mut aligned = VoxelCSG::new(5);
+        aligned.fill_obb(IVec3::new(1,2,3), Vec3::new(2.0, 1.0, 0.0), Mat3::IDENTITY);
+        let mut expected = VoxelCSG::new(5);
+        expected.fill_cube(IVec3::new(-1,1,3), IVec3::new(4,4,4));
+        assert_eq!(aligned, expected);
+    }
+
+    #[test]
+    fn test_radius_primitives_grow_the_tree() {
+        use crate::Axis;
+
+        // Height 3 holds [-4, 3] on every axis; each primitive reaches past
+        // it and grows the tree instead of losing voxels, filling exactly
+        // what it fills in a tree that was tall enough to begin with.
+        type Fill = fn(&mut VoxelCSG);
+        let fills: [(Fill, IVec3); 6] = [
+            (|c| c.fill_sphere(IVec3::ZERO, 6.0), IVec3::new(6,0,0)),
+            (|c| c.fill_sphere_shell(IVec3::new(40,0,0), 6.0, 5.0), IVec3::new(46,0,0)),
+            (|c| c.fill_cylinder(IVec3::new(0,40,0), 3, 6.0), IVec3::new(-6,40,2)),
+            (|c| c.fill_capsule(IVec3::new(0,0,40), IVec3::new(0,0,50), 6.0), IVec3::new(0,0,56)),
+            (|c| c.fill_revolve(&[(0,0), (6,0), (6,2), (0,2)], Axis::Z, IVec3::new(-40,0,0), None), IVec3::new(-46,0,2)),
+            (|c| c.fill_disc(IVec3::new(0,-40,0), 6.0, Axis::Y), IVec3::new(0,-40,-6)),
+        ];
+        for (fill, far) in fills {
+            let mut small = VoxelCSG::new(3);
+            fill(&mut small);
+            let mut roomy = VoxelCSG::new(10);
+            fill(&mut roomy);
+            assert!(small.height() > 3);
+            assert_eq!(small, roomy);
+            assert!(small.get_voxel(far), "{}", far);
+        }
+    }
+
+    // ------------------------------------------------------------
+    // 20) Shape predicates and CSG
+    // ------------------------------------------------------------
+    #[test]
+    fn test_predicates() {
+        let mut a = VoxelCSG::new(5);
+        a.fill_cube(IVec3::new(0,0,0), IVec3::new(4,4,4));
+        let mut b = VoxelCSG::new(5);
+        b.fill_cube(IVec3::new(3,3,3), IVec3::new(6,6,6));
+        let mut far = VoxelCSG::new(5);
+        far.fill_cube(IVec3::new(8,8,8), IVec3::new(9,9,9));
+
+        assert!(a.intersects(&b));
+        assert!(b.intersects(&a));
+        assert!(!a.intersects(&far));
+
+        // Solid blocks are compared whole, never voxel by voxel.
+        let mut huge = VoxelCSG::new(12);
+        huge.fill_cube(IVec3::new(-1024,-1024,-1024), IVec3::new(1024,1024,1024));
+        let mut other_half = VoxelCSG::new(12);
+        other_half.fill_cube(IVec3::new(1024,-1024,-1024), IVec3::new(2048,1024,1024));
+        assert!(!huge.intersects(&other_half));
+        let mut corner = VoxelCSG::new(12);
+        corner.fill_cube(IVec3::new(1023,1023,1023), IVec3::new(1025,1025,1025));
+        assert!(huge.intersects(&corner));
+        assert!(corner.intersects(&huge));
+        corner.set_voxel(IVec3::new(1023,1023,1023), false);
+        assert!(!huge.intersects(&corner));
+        assert!(!corner.intersects(&huge));
+
+        let mut sub = VoxelCSG::new(5);
+        sub.fill_cube(IVec3::new(1,1,1), IVec3::new(3,3,3));
+        assert!(a.contains_all(&sub));
+        assert!(a.contains_all(&a));
+        assert!(!sub.contains_all(&a));
+        let mut shifted = VoxelCSG::new(5);
+        shifted.fill_cube(IVec3::new(2,2,2), IVec3::new(5,5,5));
+        assert!(!a.contains_all(&shifted));
+        assert!(a.contains_all(&VoxelCSG::new(5)));
+    }
+
+    #[test]
+    fn test_region_is_empty() {
+        let mut csg = VoxelCSG::new(6);
+        assert!(csg.region_is_empty(IVec3::new(-32,-32,-32), IVec3::new(31,31,31)));
+
+        csg.set_voxel(IVec3::new(5,6,7), true);
+        csg.fill_cube(IVec3::new(-16,-16,-16), IVec3::new(-8,-8,-8));
+        assert!(!csg.region_is_empty(IVec3::new(5,6,7), IVec3::new(5,6,7)));
+        assert!(!csg.region_is_empty(IVec3::new(0,0,0), IVec3::new(10,10,10)));
+        assert!(!csg.region_is_empty(IVec3::new(-9,-9,-9), IVec3::new(-9,-9,-9)));
+        // Never-touched regions, including one next to filled voxels.
+        assert!(csg.region_is_empty(IVec3::new(10,10,10), IVec3::new(31,31,31)));
+        assert!(csg.region_is_empty(IVec3::new(6,6,7), IVec3::new(8,8,8)));
+        assert!(csg.region_is_empty(IVec3::new(-8,-8,-8), IVec3::new(-1,-1,-1)));
+
+        // Clearing leaves allocated-but-empty nodes behind; still empty.
+        csg.set_voxel(IVec3::new(5,6,7), false);
+        assert!(csg.region_is_empty(IVec3::new(0,0,0), IVec3::new(10,10,10)));
+    }
+
+    #[test]
+    fn test_overlap_region() {
+        let mut a = VoxelCSG::new(4);
+        a.fill_cube(IVec3::new(0,0,0), IVec3::new(4,4,4));
+        let mut b = VoxelCSG::new(4);
+        b.fill_cube(IVec3::new(2,1,3), IVec3::new(6,6,6));
+        assert_eq!(a.overlap_region(&b), Some((IVec3::new(2,1,3), IVec3::new(3,3,3))));
+        assert_eq!(a.intersection(&b).count_filled(), 6);
+
+        // Disjoint cubes: no overlap, and the intersection is empty.
+        let mut c = VoxelCSG::new(4);
+        c.fill_cube(IVec3::new(-6,-6,-6), IVec3::new(-2,-2,-2));
+        assert_eq!(a.overlap_region(&c), None);
+        assert_eq!(a.intersection(&c).count_filled(), 0);
+        assert_eq!(a.overlap_region(&VoxelCSG::new(4)), None);
+    }
+
+    #[test]
+    fn test_intersection_overlap_only() {
+        // Far-apart cubes: nothing in common.
+        let mut a = VoxelCSG::new(8);
+        a.fill_cube(IVec3::new(-100,-100,-100), IVec3::new(-90,-90,-90));
+        let mut b = VoxelCSG::new(8);
+        b.fill_cube(IVec3::new(90,90,90), IVec3::new(100,100,100));
+        assert_eq!(a.intersection(&b).count_filled(), 0);
+
+        // A large scattered shape against a small cube: only the overlap
+        // region is traversed, and the result matches a per-voxel check.
+        let mut big = VoxelCSG::new(6);
+        big.fill_lattice(IVec3::new(-30,-30,-30), IVec3::new(30,30,30), IVec3::new(1,2,3));
+        let mut small = VoxelCSG::new(6);
+        small.fill_cube(IVec3::new(5,5,5), IVec3::new(9,9,9));
+        let result = big.intersection(&small);
+        let expected = big.iter_filled().filter(|&p| small.get_voxel(p)).count();
+        assert!(expected > 0);
+        assert_eq!(result.count_filled(), expected);
+        assert!(result.iter_filled().all(|p| big.get_voxel(p) && small.get_voxel(p)));
+        assert_eq!(small.intersection(&big), result);
+    }
+
+    #[test]
+    fn test_csg_operators() {
+        let mut a = VoxelCSG::new(4);
+        a.fill_cube(IVec3::new(0,0,0), IVec3::new(4,4,4));
+        let mut b = VoxelCSG::new(4);
+        b.fill_sphere(IVec3::new(4,4,4), 3.0);
+
+        assert_eq!(&a + &b, a.union(&b));
+        assert_eq!(&a - &b, a.difference(&b));
+        assert_eq!(&a & &b, a.intersection(&b));
+        assert_eq!(&a ^ &b, a.symmetric_difference(&b));
+
+        let xor = &a ^ &b;
+        assert_eq!(xor, (&a - &b).union(&(&b - &a)));
+        assert_eq!(xor.count_filled() + 2 * (&a & &b).count_filled(), a.count_filled() + b.count_filled());
+    }
+
+    #[test]
+    fn test_csg_keeps_blocks_summarized() {
+        // CSG on large solid blocks works block by block: every result stays
+        // proportional to its surface instead of expanding to leaves.
+        let mut a = VoxelCSG::new(9);
+        a.fill_cube(IVec3::new(-50,-50,-50), IVec3::new(50,50,50));
+        let mut b = VoxelCSG::new(9);
+        b.fill_cube(IVec3::new(0,0,0), IVec3::new(100,100,100));
+        let surface = 6 * 150 * 150;
+        let volume = 100 * 100 * 100;
+
+        let union = a.union(&b);
+        assert_eq!(union.count_filled(), 2 * volume - 50 * 50 * 50);
+        assert!(union.node_count() < surface, "{} nodes", union.node_count());
+
+        let difference = a.difference(&b);
+        assert_eq!(difference.count_filled(), volume - 50 * 50 * 50);
+        assert!(difference.node_count() < surface);
+        assert!(!difference.get_voxel(IVec3::new(0,0,0)));
+        assert!(difference.get_voxel(IVec3::new(-1,0,0)));
+
+        let intersection = a.intersection(&b);
+        assert_eq!(intersection.count_filled(), 50 * 50 * 50);
+        assert!(intersection.node_count() < surface);
+
+        let mut in_place = a.clone();
+        in_place.union_with(&b);
+        in_place.subtract(&intersection);
+        assert_eq!(in_place, &union - &intersection);
+        assert!(in_place.node_count() < 2 * surface);
+        in_place.intersect_with(&a);
+        assert_eq!(in_place, difference);
+
+        let moved = a.translate(IVec3::new(7,-3,5));
+        assert_eq!(moved.count_filled(), volume);
+        assert!(moved.get_voxel(IVec3::new(56,46,54)));
+        assert!(moved.node_count() < surface);
+
+        let scaled = a.crop(IVec3::new(0,0,0), IVec3::new(24,24,24)).scale(IVec3::new(2,1,3));
+        assert_eq!(scaled.count_filled(), 25 * 25 * 25 * 6);
+        assert_eq!(scaled.bounding_box(), Some((IVec3::ZERO, IVec3::new(49,24,74))));
+        assert!(scaled.node_count() < surface);
+
+        let mut streamed = 0;
+        for coords in a.iter_filled().take(1000) {
+            assert!(a.get_voxel(coords));
+            streamed += 1;
+        }
+        assert_eq!(streamed, 1000);
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut csg = VoxelCSG::new(5);
+        csg.fill_cube(IVec3::new(0,0,0), IVec3::new(6,4,4));
+        let before = csg.count_filled();
+
+        csg.retain(|c| c.x % 2 == 0);
+        assert_eq!(csg.count_filled(), before / 2);
+        assert!(csg.get_voxel(IVec3::new(2,1,1)));
+        assert!(!csg.get_voxel(IVec3::new(3,1,1)));
+        assert!(csg.iter_filled().all(|c| c.x % 2 == 0));
+        // Cleared leaves are pruned.
+        assert!(csg.node_count() < before);
+    }
+
+    #[test]
+    fn test_clip_plane() {
+        use grid_tree::glam::Vec3;
+
+        let mut sphere = VoxelCSG::new(5);
+        sphere.fill_sphere(IVec3::ZERO, 6.0);
+        let lower = sphere.iter_filled().filter(|c| c.y <= 0).count();
+        assert!(lower < sphere.count_filled());
+
+        sphere.clip_plane(Vec3::Y, 0.0);
+        assert_eq!(sphere.count_filled(), lower);
+        assert!(sphere.iter_filled().all(|c| c.y <= 0));
+        assert!(sphere.get_voxel(IVec3::new(0,-6,0)));
+        assert!(!sphere.get_voxel(IVec3::new(0,6,0)));
+    }
+
+    #[test]
+    fn test_split() {
+        let mut cube = VoxelCSG::new(5);
+        cube.fill_cube(IVec3::new(0,0,0), IVec3::new(8,8,8));
+        let mut sphere = VoxelCSG::new(5);
+        sphere.fill_sphere(IVec3::new(8,8,8), 5.0);
+
+        let (kept, removed) = cube.split(&sphere);
+        assert_eq!(kept.count_filled() + removed.count_filled(), cube.count_filled());
+        assert!(removed.count_filled() > 0);
+        assert_eq!(kept, cube.difference(&sphere));
+        assert_eq!(removed, cube.intersection(&sphere));
+    }
+
+    // ------------------------------------------------------------
+    // 21) More transforms
+    // ------------------------------------------------------------
+    #[test]
+    fn test_rotate() {
+        use crate::Axis;
+
+        // A thin bar along +X.
+        let mut bar = VoxelCSG::new(6);
+        bar.fill_cube(IVec3::new(1,0,0), IVec3::new(9,1,1));
+
+        let exact = bar.rotate90(Axis::Z, 1);
+        assert!(exact.get_voxel(IVec3::new(0,1,0)));
+        assert!(exact.get_voxel(IVec3::new(0,8,0)));
+        assert_eq!(exact.count_filled(), 8);
+        assert_eq!(bar.rotate90(Axis::Z, 4), bar);
+        assert_eq!(bar.rotate90(Axis::Z, -1), bar.rotate90(Axis::Z, 3));
+        assert!(bar.rotate90(Axis::Y, 1).get_voxel(IVec3::new(0,0,-8)));
+
+        // The resampled path agrees up to a few boundary voxels.
+        let resampled = bar.rotate(Axis::Z, std::f32::consts::FRAC_PI_2);
+        let mismatches = resampled.iter_filled().filter(|&c| !exact.get_voxel(c)).count()
+            + exact.iter_filled().filter(|&c| !resampled.get_voxel(c)).count();
+        assert!(mismatches <= 2, "{} mismatches", mismatches);
+
+        // A 30 degree turn keeps roughly the same amount of material.
+        let tilted = bar.rotate(Axis::Z, 30f32.to_radians());
+        assert!((6..=10).contains(&tilted.count_filled()));
+    }
+
+    #[test]
+    fn test_scale_about() {
+        let mut cube = VoxelCSG::new(5);
+        cube.fill_cube(IVec3::new(10,10,10), IVec3::new(15,15,15));
+        let pivot = IVec3::splat(12);
+
+        let scaled = cube.scale_about(IVec3::splat(2), pivot);
+        assert_eq!(scaled.count_filled(), 10 * 10 * 10);
+        // Grows around the pivot: 4 voxels below it, 5 above (the pivot
+        // voxel's own block is [12, 13]).
+        assert_eq!(scaled.bounding_box(), Some((IVec3::splat(8), IVec3::splat(17))));
+        // Scaling about the origin instead pushes it away.
+        assert_eq!(cube.scale(IVec3::splat(2)).bounding_box(), Some((IVec3::splat(20), IVec3::splat(29))));
+
+        // Unit factors leave the shape unchanged.
+        assert_eq!(cube.scale_about(IVec3::ONE, pivot), cube);
+        assert_eq!(cube.translate(IVec3::new(1,0,-1)).bounding_box(),
+                   Some((IVec3::new(11,10,9), IVec3::new(15,14,13))));
+    }
+
+    #[test]
+    fn test_world_transform() {
+        use crate::WorldTransform;
+        use grid_tree::glam::Vec3;
+
+        let t = WorldTransform::new(0.5, Vec3::new(10.0, 0.0, 0.0));
+        assert_eq!(t.world_to_voxel(Vec3::new(11.0, 2.2, -0.7)), IVec3::new(2, 4, -1));
+        assert_eq!(t.voxel_to_world(IVec3::new(2, 4, -1)), Vec3::new(11.0, 2.0, -0.5));
+        for c in [IVec3::ZERO, IVec3::new(-7, 3, 12)] {
+            assert_eq!(t.world_to_voxel(t.voxel_to_world(c)), c);
+        }
+        assert_eq!(t.length_to_voxels(3.0), 6.0);
+
+        // A 1.5-unit sphere at world (11, 0, 0) is a 3-voxel sphere at (2, 0, 0).
+        let mut csg = VoxelCSG::new(4);
+        csg.fill_sphere(t.world_to_voxel(Vec3::new(11.0, 0.0, 0.0)), t.length_to_voxels(1.5));
+        assert!(csg.get_voxel(IVec3::new(5, 0, 0)));
+        assert!(!csg.get_voxel(IVec3::new(6, 0, 0)));
+    }
+
+    #[test]
+    fn test_tile() {
+        let unit = VoxelCSG::from_coords([IVec3::new(0,0,0)]);
+        let row = unit.tile(IVec3::new(3,1,1), IVec3::new(2,0,0));
+        assert_eq!(row.count_filled(), 3);
+        for x in [0, 2, 4] {
+            assert!(row.get_voxel(IVec3::new(x,0,0)));
+        }
+
+        let mut cell = VoxelCSG::new(4);
+        cell.fill_cube(IVec3::new(0,0,0), IVec3::new(2,2,2));
+        let lattice = cell.tile(IVec3::new(2,3,2), IVec3::new(4,4,4));
+        assert_eq!(lattice.count_filled(), 8 * 12);
+        assert!(lattice.get_voxel(IVec3::new(5,9,5)));
+        assert!(!lattice.get_voxel(IVec3::new(2,0,0)));
+        assert_eq!(cell.tile(IVec3::new(0,1,1), IVec3::ONE).count_filled(), 0);
+    }
+
+    #[test]
+    fn test_radial_symmetry() {
+        use crate::Axis;
+
+        let mut blade = VoxelCSG::new(4);
+        blade.set_voxel(IVec3::new(3,1,2), true);
+
+        let fan = blade.radial_symmetry(Axis::Z, 4);
+        assert_eq!(fan.count_filled(), 4);
+        for p in [IVec3::new(3,1,2), IVec3::new(-1,3,2), IVec3::new(-3,-1,2), IVec3::new(1,-3,2)] {
+            assert!(fan.get_voxel(p), "{:?}", p);
+        }
+        assert_eq!(blade.radial_symmetry(Axis::Z, 1), blade);
+
+        // Three folds go through the lossy arbitrary-angle rotation: every
+        // copy lands next to its ideal position at 120 and 240 degrees.
+        let mut arm = VoxelCSG::new(4);
+        arm.set_voxel(IVec3::new(4,0,0), true);
+        let tri = arm.radial_symmetry(Axis::Z, 3);
+        let ideal = [IVec3::new(4,0,0), IVec3::new(-2,3,0), IVec3::new(-2,-3,0)];
+        assert!(ideal.iter().all(|&p| tri.get_voxel(p)));
+        assert!(tri.iter_filled().all(|p| ideal.iter().any(|&q| (p - q).abs().max_element() <= 1)));
+    }
+
+    #[test]
+    fn test_smooth_step() {
+        use crate::Connectivity;
+
+        // Sparse noise: about one voxel in five filled.
+        let mut noise = VoxelCSG::new(5);
+        noise.fill_density(IVec3::ZERO, IVec3::splat(11), |p| {
+            let hash = (p.x as u32).wrapping_mul(73856093)
+                ^ (p.y as u32).wrapping_mul(19349663)
+                ^ (p.z as u32).wrapping_mul(83492791);
+            (hash.wrapping_mul(2654435761) >> 24) as f32
+        }, 205.0);
+        let isolated = |csg: &VoxelCSG| {
+            csg.iter_filled()
+                .filter(|&p| csg.filled_neighbor_count(p, Connectivity::Face6) == 0)
+                .count()
+        };
+        assert!(isolated(&noise) > 0);
+
+        let smoothed = noise.smooth_step(4, 2, Connectivity::Face6);
+        assert!(isolated(&smoothed) < isolated(&noise));
+        // Every survivor had company, every birth a crowd.
+        for p in smoothed.iter_filled() {
+            let count = noise.filled_neighbor_count(p, Connectivity::Face6);
+            assert!(count >= if noise.get_voxel(p) { 2 } else { 4 });
+        }
+
+        assert_eq!(VoxelCSG::new(3).smooth_step(1, 1, Connectivity::Vertex26).count_filled(), 0);
+    }
+
+    // ------------------------------------------------------------
+    // 22) Analysis
+    // ------------------------------------------------------------
+    #[test]
+    fn test_fill_ratio() {
+        let mut cube = VoxelCSG::new(5);
+        cube.fill_cube(IVec3::new(0,0,0), IVec3::new(8,8,8));
+        // Cut away everything at x >= 4.
+        let mut cut = VoxelCSG::new(5);
+        cut.fill_cube(IVec3::new(4,0,0), IVec3::new(8,8,8));
+        let half = cube.difference(&cut);
+
+        let ratio = half.fill_ratio(IVec3::new(0,0,0), IVec3::new(7,7,7));
+        assert!((ratio - 0.5).abs() < 1e-6);
+        assert_eq!(cube.fill_ratio(IVec3::new(0,0,0), IVec3::new(7,7,7)), 1.0);
+        assert_eq!(half.fill_ratio(IVec3::new(4,0,0), IVec3::new(7,7,7)), 0.0);
+        assert_eq!(half.fill_ratio(IVec3::new(1,1,1), IVec3::new(0,0,0)), 0.0);
+
+        // Counted from summarized blocks, so huge regions stay cheap.
+        let mut big = VoxelCSG::new(4);
+        big.fill_cube(IVec3::ZERO, IVec3::splat(1 << 20));
+        assert_eq!(big.fill_ratio(IVec3::ZERO, IVec3::splat((1 << 21) - 1)), 0.125);
+        assert_eq!(big.fill_ratio(IVec3::splat(i32::MIN), IVec3::splat(-1)), 0.0);
+    }
+
+    #[test]
+    fn test_connectivity() {
+        use crate::Connectivity;
+
+        assert_eq!(Connectivity::Face6.offsets().len(), 6);
+        assert_eq!(Connectivity::Edge18.offsets().len(), 18);
+        assert_eq!(Connectivity::Vertex26.offsets().len(), 26);
+
+        // Two voxels touching only at a corner.
+        let corner = VoxelCSG::from_coords([IVec3::new(0,0,0), IVec3::new(1,1,1)]);
+        assert_eq!(corner.connected_components(Connectivity::Face6).len(), 2);
+        assert_eq!(corner.connected_components(Connectivity::Edge18).len(), 2);
+        assert_eq!(corner.connected_components(Connectivity::Vertex26).len(), 1);
+
+        // Touching along an edge.
+        let edge = VoxelCSG::from_coords([IVec3::new(0,0,0), IVec3::new(1,1,0)]);
+        assert_eq!(edge.connected_components(Connectivity::Face6).len(), 2);
+        assert_eq!(edge.connected_components(Connectivity::Edge18).len(), 1);
+
+        let filled = corner.flood_fill(IVec3::new(0,0,0), Connectivity::Vertex26);
+        assert_eq!(filled, corner);
+        assert_eq!(corner.flood_fill(IVec3::new(5,5,5), Connectivity::Vertex26).count_filled(), 0);
+
+        // Dilation: a cross versus a cube.
+        let single = VoxelCSG::from_coords([IVec3::new(0,0,0)]);
+        assert_eq!(single.dilate(Connectivity::Face6).count_filled(), 7);
+        assert_eq!(single.dilate(Connectivity::Vertex26).count_filled(), 27);
+    }
+
+    #[test]
+    fn test_surface_voxels() {
+        let mut cube = VoxelCSG::new(5);
+        cube.fill_cube(IVec3::new(0,0,0), IVec3::new(5,5,5));
+        let surface = cube.surface_voxels();
+        assert_eq!(surface.count_filled(), 125 - 27);
+        assert!(!surface.get_voxel(IVec3::new(2,2,2)));
+        assert!(surface.get_voxel(IVec3::new(0,2,2)));
+        assert!(cube.contains_all(&surface));
+    }
+
+    #[test]
+    fn test_occluded() {
+        use grid_tree::glam::Vec3;
+
+        let mut csg = VoxelCSG::new(5);
+        csg.fill_cube(IVec3::new(4,0,0), IVec3::new(6,2,2));
+
+        assert!(csg.occluded(Vec3::new(0.0,1.0,1.0), Vec3::X, 10.0));
+        // Too short to reach the block at x = 3.5.
+        assert!(!csg.occluded(Vec3::new(0.0,1.0,1.0), Vec3::X, 3.0));
+        // Parallel ray through empty space.
+        assert!(!csg.occluded(Vec3::new(0.0,5.0,1.0), Vec3::X, 20.0));
+        // Pointing away.
+        assert!(!csg.occluded(Vec3::new(0.0,1.0,1.0), -Vec3::X, 20.0));
+        // Diagonal ray into the block; direction need not be normalized.
+        assert!(csg.occluded(Vec3::new(0.0,-3.0,1.0), Vec3::new(2.0,2.0,0.0), 10.0));
+        assert!(csg.occluded(Vec3::new(4.2,1.0,1.0), Vec3::ZERO, 0.0));
+
+        // Rays that miss the shape stop at its bounding box, however long.
+        assert!(!csg.occluded(Vec3::new(0.0,9.0,1.0), Vec3::X, f32::MAX));
+        assert!(!csg.occluded(Vec3::new(0.0,1.0,1.0), Vec3::new(1.0,0.0,1.0), 1e30));
+        assert!(!csg.occluded(Vec3::new(9.0,1.0,1.0), Vec3::X, f32::MAX));
+        // A long ray from far away still finds the block.
+        assert!(csg.occluded(Vec3::new(-1e6,1.0,1.0), Vec3::X, 1e7));
+        assert!(!VoxelCSG::new(5).occluded(Vec3::ZERO, Vec3::X, 1e30));
+    }
+
+    #[test]
+    #[should_panic(expected = "max_dist must be finite")]
+    fn test_occluded_rejects_infinite_distance() {
+        use grid_tree::glam::Vec3;
+
+        let mut csg = VoxelCSG::new(5);
+        csg.fill_cube(IVec3::new(4,0,0), IVec3::new(6,2,2));
+        csg.occluded(Vec3::new(0.0,9.0,1.0), Vec3::X, f32::INFINITY);
+    }
+
+    #[test]
+    #[should_panic(expected = "max_dist must be finite")]
+    fn test_occluded_rejects_nan_distance() {
+        use grid_tree::glam::Vec3;
+
+        VoxelCSG::new(5).occluded(Vec3::ZERO, Vec3::X, f32::NAN);
+    }
+
+    #[test]
+    fn test_centroid() {
+        use grid_tree::glam::Vec3;
+
+        assert_eq!(VoxelCSG::new(4).centroid(), None);
+
+        let mut cube = VoxelCSG::new(5);
+        cube.fill_cube(IVec3::new(-3,0,2), IVec3::new(5,8,6));
+        let c = cube.centroid().unwrap();
+        assert!((c - Vec3::new(0.5, 3.5, 3.5)).length() < 1e-5);
+
+        // An L: a long arm along +X and a short one along +Y.
+        let mut l = VoxelCSG::new(5);
+        l.fill_cube(IVec3::new(0,0,0), IVec3::new(10,1,1));
+        l.fill_cube(IVec3::new(0,1,0), IVec3::new(1,4,1));
+        let c = l.centroid().unwrap();
+        assert!(c.x > c.y);
+        assert!((c.x - 45.0 / 13.0).abs() < 1e-5);
+        assert!((c.y - 6.0 / 13.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_bounding_sphere() {
+        use grid_tree::glam::Vec3;
+
+        assert_eq!(VoxelCSG::new(4).bounding_sphere(), None);
+
+        let mut sphere = VoxelCSG::new(6);
+        sphere.fill_sphere(IVec3::new(3,-2,5), 8.0);
+        let (center, radius) = sphere.bounding_sphere().unwrap();
+        assert!(center.distance(Vec3::new(3.0,-2.0,5.0)) < 1.0);
+        assert!((radius - 8.0).abs() < 0.8, "radius {}", radius);
+        for c in sphere.iter_filled() {
+            assert!(c.as_vec3().distance(center) <= radius + 1e-3);
+        }
+
+        let single = VoxelCSG::from_coords([IVec3::new(1,2,3)]);
+        assert_eq!(single.bounding_sphere(), Some((Vec3::new(1.0,2.0,3.0), 0.0)));
+    }
+
+    #[test]
+    fn test_filled_neighbor_count() {
+        use crate::Connectivity;
+
+        let mut csg = VoxelCSG::new(4);
+        csg.fill_cube(IVec3::new(0,0,0), IVec3::new(5,5,5));
+        let inside = IVec3::new(2,2,2);
+        assert_eq!(csg.filled_neighbor_count(inside, Connectivity::Face6), 6);
+        assert_eq!(csg.filled_neighbor_count(inside, Connectivity::Edge18), 18);
+        assert_eq!(csg.filled_neighbor_count(inside, Connectivity::Vertex26), 26);
+
+        // A corner of the block sees 3 face, 6 edge and 7 vertex neighbors.
+        assert_eq!(csg.filled_neighbor_count(IVec3::ZERO, Connectivity::Face6), 3);
+        assert_eq!(csg.filled_neighbor_count(IVec3::ZERO, Connectivity::Edge18), 6);
+        assert_eq!(csg.filled_neighbor_count(IVec3::ZERO, Connectivity::Vertex26), 7);
+
+        let mut lone = VoxelCSG::new(4);
+        lone.set_voxel(IVec3::new(-3,1,2), true);
+        assert_eq!(lone.filled_neighbor_count(IVec3::new(-3,1,2), Connectivity::Vertex26), 0);
+
+        // Agrees with probing each neighbor.
+        let mut ball = VoxelCSG::new(4);
+        ball.fill_sphere(IVec3::new(1,1,1), 3.5);
+        for p in [IVec3::new(4,1,1), IVec3::new(3,3,2), IVec3::new(-2,0,1)] {
+            let probed = ball.neighbors26(p).iter().filter(|&&f| f).count() as u8;
+            assert_eq!(ball.filled_neighbor_count(p, Connectivity::Vertex26), probed);
+        }
+    }
+
+    #[test]
+    fn test_remove_small_components() {
+        use crate::Connectivity;
+
+        let mut csg = VoxelCSG::new(5);
+        csg.fill_cube(IVec3::new(0,0,0), IVec3::new(3,3,3));
+        csg.set_voxel(IVec3::new(6,6,6), true);
+        csg.set_voxel(IVec3::new(6,6,7), true);
+
+        csg.remove_small_components(5, Connectivity::Face6);
+        assert_eq!(csg.count_filled(), 27);
+        assert!(!csg.get_voxel(IVec3::new(6,6,6)));
+        assert_eq!(csg.connected_components(Connectivity::Face6).len(), 1);
+
+        // A speck touching only diagonally counts as its own piece under Face6.
+        let mut diagonal = VoxelCSG::new(5);
+        diagonal.fill_cube(IVec3::new(0,0,0), IVec3::new(3,3,3));
+        diagonal.set_voxel(IVec3::new(3,3,3), true);
+        let mut kept = diagonal.clone();
+        kept.remove_small_components(5, Connectivity::Vertex26);
+        assert_eq!(kept.count_filled(), 28);
+        diagonal.remove_small_components(5, Connectivity::Face6);
+        assert_eq!(diagonal.count_filled(), 27);
+    }
+
+    #[test]
+    fn test_enclosed_cavities() {
+        use crate::FaceMask;
+
+        // Closed 6x6x6 shell: the 4x4x4 interior is trapped.
+        let mut closed = VoxelCSG::new(4);
+        closed.fill_box_walls(IVec3::new(0,0,0), IVec3::new(6,6,6), 1, FaceMask::NONE);
+        let cavities = closed.enclosed_cavities();
+        assert_eq!(cavities.count_filled(), 64);
+        assert!(cavities.get_voxel(IVec3::new(1,1,1)));
+        assert!(cavities.get_voxel(IVec3::new(4,4,4)));
+        assert!(!cavities.get_voxel(IVec3::new(0,0,0)));
+
+        // Same box with the top open drains.
+        let mut open = VoxelCSG::new(4);
+        open.fill_box_walls(IVec3::new(0,0,0), IVec3::new(6,6,6), 1, FaceMask::POS_Z);
+        assert_eq!(open.enclosed_cavities().count_filled(), 0);
+
+        assert_eq!(VoxelCSG::new(3).enclosed_cavities().count_filled(), 0);
+    }
+
+    #[test]
+    fn test_stats() {
+        use crate::{Connectivity, VoxelStats};
+
+        let mut csg = VoxelCSG::new(5);
+        csg.fill_cube(IVec3::new(0,0,0), IVec3::new(4,3,2));
+        csg.fill_cube(IVec3::new(6,0,0), IVec3::new(7,1,5));
+
+        let stats = csg.stats();
+        assert_eq!(stats, VoxelStats {
+            filled: csg.count_filled(),
+            bounding_box: csg.bounding_box(),
+            node_count: csg.node_count(),
+            components: csg.connected_components(Connectivity::Face6).len(),
+            surface_area: stats.surface_area,
+        });
+        assert_eq!(stats.filled, 24 + 5);
+        assert_eq!(stats.bounding_box, Some((IVec3::new(0,0,0), IVec3::new(6,2,4))));
+        assert_eq!(stats.components, 2);
+        // 2(ab + bc + ca) per box.
+        assert_eq!(stats.surface_area, 2 * (12 + 6 + 8) + 2 * (1 + 5 + 5));
+
+        let empty = VoxelCSG::new(3).stats();
+        assert_eq!((empty.filled, empty.bounding_box, empty.components, empty.surface_area), (0, None, 0, 0));
+    }
+
+    #[test]
+    fn test_sample_line() {
+        let mut csg = VoxelCSG::new(5);
+        csg.fill_cube(IVec3::new(0,0,0), IVec3::new(6,6,6));
+
+        // Straight through along x: 3 empty, 6 filled, 3 empty.
+        let profile = csg.sample_line(IVec3::new(-3,2,2), IVec3::new(8,2,2));
+        assert_eq!(profile.len(), 12);
+        let filled: Vec<usize> = (0..profile.len()).filter(|&i| profile[i]).collect();
+        assert_eq!(filled, (3..9).collect::<Vec<_>>());
+
+        // A diagonal steps once per voxel along its longest axis.
+        let diagonal = csg.sample_line(IVec3::new(-2,-2,-2), IVec3::new(8,8,8));
+        assert_eq!(diagonal.len(), 11);
+        assert_eq!(diagonal.iter().filter(|&&f| f).count(), 6);
+        assert!(!diagonal[0] && !diagonal[10]);
+
+        assert_eq!(csg.sample_line(IVec3::new(1,1,1), IVec3::new(1,1,1)), vec![true]);
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut before = VoxelCSG::new(5);
+        before.fill_cube(IVec3::new(0,0,0), IVec3::new(3,3,3));
+        let mut after = before.clone();
+        after.set_voxel(IVec3::new(3,0,0), true);
+        after.set_voxel(IVec3::new(1,1,1), false);
+
+        let (added, removed) = before.diff(&after);
+        assert_eq!(added, vec![IVec3::new(3,0,0)]);
+        assert_eq!(removed, vec![IVec3::new(1,1,1)]);
+
+        let (added, removed) = after.diff(&before);
+        assert_eq!(added, vec![IVec3::new(1,1,1)]);
+        assert_eq!(removed, vec![IVec3::new(3,0,0)]);
+
+        let (added, removed) = before.diff(&before);
+        assert!(added.is_empty() && removed.is_empty());
+    }
+
+    #[test]
+    fn test_iter_chunks() {
+        let mut csg = VoxelCSG::new(6);
+        // x in 5..11 straddles the chunk boundary at x = 8.
+        csg.fill_cube(IVec3::new(5,0,0), IVec3::new(11,2,2));
+
+        let chunks: Vec<_> = csg.iter_chunks(8).collect();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].0, IVec3::new(0,0,0));
+        assert_eq!(chunks[0].1.count_filled(), 3 * 2 * 2);
+        assert_eq!(chunks[1].0, IVec3::new(8,0,0));
+        assert_eq!(chunks[1].1.count_filled(), 3 * 2 * 2);
+        assert!(chunks[1].1.get_voxel(IVec3::new(10,1,1)));
+
+        // Negative coordinates land in negative-origin chunks.
+        let negative = VoxelCSG::from_coords([IVec3::new(-1,0,0)]);
+        let chunks: Vec<_> = negative.iter_chunks(8).collect();
+        assert_eq!(chunks[0].0, IVec3::new(-8,0,0));
+    }
+
+    #[test]
+    fn test_iter_slices() {
+        use crate::Axis;
+
+        let mut csg = VoxelCSG::new(4);
+        csg.fill_cube(IVec3::new(0,0,2), IVec3::new(2,2,3));
+        csg.set_voxel(IVec3::new(5,-1,-3), true);
+
+        let slices: Vec<(i32, Vec<IVec3>)> = csg.iter_slices(Axis::Z).collect();
+        assert_eq!(slices.len(), 2);
+        assert_eq!(slices[0], (-3, vec![IVec3::new(5,-1,-3)]));
+        assert_eq!(slices[1], (2, vec![
+            IVec3::new(0,0,2), IVec3::new(1,0,2), IVec3::new(0,1,2), IVec3::new(1,1,2),
+        ]));
+
+        let by_x: Vec<i32> = csg.iter_slices(Axis::X).map(|(layer, _)| layer).collect();
+        assert_eq!(by_x, vec![0, 1, 5]);
+        assert_eq!(VoxelCSG::new(3).iter_slices(Axis::Y).count(), 0);
+    }
+
+    // ------------------------------------------------------------
+    // 23) Tree storage and coordinate limits
+    // ------------------------------------------------------------
+    #[test]
+    fn test_compact() {
+        // Voxels set and cleared one by one stay allocated as empty leaves.
+        let mut csg = VoxelCSG::new(6);
+        for filled in [true, false] {
+            for z in 0..16 {
+                for y in 0..16 {
+                    for x in 0..16 {
+                        csg.set_voxel(IVec3::new(x,y,z), filled);
+                    }
+                }
+            }
+        }
+        csg.set_voxel(IVec3::new(20,20,20), true);
+
+        let before = csg.node_count();
+        assert_eq!(csg.count_filled(), 1);
+        csg.compact();
+        assert_eq!(csg.count_filled(), 1);
+        assert!(csg.get_voxel(IVec3::new(20,20,20)));
+        assert_eq!(csg.node_count(), 6);
+        assert!(csg.node_count() * 100 < before);
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut csg = VoxelCSG::new(5);
+        csg.fill_cube(IVec3::new(-4,-4,-4), IVec3::new(9,3,2));
+        csg.fill_sphere(IVec3::new(20,0,0), 2.0);
+        let height = csg.height();
+
+        csg.clear();
+        assert_eq!(csg.count_filled(), 0);
+        assert_eq!(csg.node_count(), 0);
+        assert_eq!(csg.height(), height);
+        assert_eq!(csg, VoxelCSG::new(height));
+
+        // Nothing from before the clear comes back with new fills.
+        csg.fill_cube(IVec3::new(0,0,0), IVec3::new(2,2,2));
+        assert_eq!(csg.count_filled(), 8);
+        assert!(csg.get_voxel(IVec3::new(1,1,1)));
+        assert!(!csg.get_voxel(IVec3::new(-4,-4,-4)));
+        assert!(!csg.get_voxel(IVec3::new(20,0,0)));
+    }
+
+    #[test]
+    fn test_try_get_voxel() {
+        use crate::OutOfBounds;
+
+        // Height 4 represents [-8, 7] on each axis.
+        let mut csg = VoxelCSG::new(4);
+        csg.set_voxel(IVec3::new(7,-8,0), true);
+        assert_eq!(csg.try_get_voxel(IVec3::new(7,-8,0)), Ok(true));
+        assert_eq!(csg.try_get_voxel(IVec3::new(0,0,0)), Ok(false));
+        assert_eq!(
+            csg.try_get_voxel(IVec3::new(8,0,0)),
+            Err(OutOfBounds { coords: IVec3::new(8,0,0), height: 4 })
+        );
+        assert!(csg.try_get_voxel(IVec3::new(0,0,-9)).is_err());
+        assert!(!csg.get_voxel(IVec3::new(100,0,0)));
+    }
+
+    #[test]
+    fn test_grow_to_contain() {
+        let mut csg = VoxelCSG::new(4);
+        csg.fill_cube(IVec3::new(0,0,0), IVec3::new(2,2,2));
+
+        // Far outside [-8, 7]: the tree grows instead of losing the voxel.
+        csg.set_voxel(IVec3::new(1000,-3,5), true);
+        assert!(csg.get_voxel(IVec3::new(1000,-3,5)));
+        assert_eq!(csg.try_get_voxel(IVec3::new(1000,-3,5)), Ok(true));
+        assert_eq!(csg.height(), 11);
+        assert_eq!(csg.count_filled(), 9);
+
+        csg.fill_cube(IVec3::new(-5000,0,0), IVec3::new(-4998,1,1));
+        assert!(csg.get_voxel(IVec3::new(-4999,0,0)));
+        assert_eq!(csg.height(), 14);
+
+        // Growing explicitly keeps existing voxels; shrinking never happens.
+        let before = csg.clone();
+        csg.grow_to_contain(IVec3::new(0,1 << 20,0));
+        assert_eq!(csg.height(), 22);
+        assert_eq!(csg, before);
+        csg.grow_to_contain(IVec3::ZERO);
+        assert_eq!(csg.height(), 22);
+    }
+
+    #[test]
+    fn test_coordinate_bounds() {
+        assert_eq!(VoxelCSG::coordinate_bounds(4), (IVec3::splat(-8), IVec3::splat(7)));
+        for height in 2..31 {
+            let (min, max) = VoxelCSG::coordinate_bounds(height);
+            let (next_min, next_max) = VoxelCSG::coordinate_bounds(height + 1);
+            assert_eq!(next_min, min * 2);
+            assert_eq!(next_max + IVec3::ONE, (max + IVec3::ONE) * 2);
+        }
+        assert_eq!(VoxelCSG::coordinate_bounds(32), (IVec3::splat(i32::MIN), IVec3::splat(i32::MAX)));
+    }
+
+    #[test]
+    fn test_primitives_clamp_at_i32_limits() {
+        use crate::Axis;
+
+        // The bounding boxes reach past i32::MAX / i32::MIN; they are clamped
+        // rather than wrapped, so only the representable part is filled.
+        let top = IVec3::splat(i32::MAX - 1);
+        let mut csg = VoxelCSG::new(4);
+        let added = csg.fill_sphere_counted(top, 3.0);
+        assert!(added > 0);
+        assert!(csg.get_voxel(top));
+        assert!(csg.get_voxel(IVec3::splat(i32::MAX)));
+        assert!(csg.get_voxel(top - IVec3::new(3,0,0)));
+        assert!(csg.iter_filled().all(|p| (p - top).as_vec3().length() <= 3.0));
+
+        let bottom = IVec3::splat(i32::MIN + 1);
+        let mut csg = VoxelCSG::new(4);
+        csg.fill_capsule(bottom, bottom + IVec3::new(0,0,4), 2.0);
+        csg.fill_disc(bottom, 2.0, Axis::X);
+        csg.fill_cylinder(bottom, 3, 2.0);
+        assert!(csg.get_voxel(IVec3::splat(i32::MIN)));
+        assert!(csg.get_voxel(bottom + IVec3::new(0,2,4)));
+        assert!(csg.iter_filled().all(|p| p.cmpge(IVec3::splat(i32::MIN)).all()));
+    }
+
+    #[test]
+    fn test_region_ops_at_i32_limits() {
+        // Reaching i32::MAX grows the tree to height 32, whose roots sit at
+        // level 31: their exclusive corners don't fit in an i32.
+        let top = IVec3::splat(i32::MAX - 3);
+        let mut csg = VoxelCSG::new(4);
+        csg.fill_cube(top, IVec3::splat(i32::MAX));
+        assert_eq!(csg.height(), 32);
+        assert_eq!(csg.count_filled(), 27);
+        assert!(csg.get_voxel(IVec3::splat(i32::MAX - 1)));
+        assert!(!csg.get_voxel(IVec3::splat(i32::MAX)));
+
+        assert!(!csg.region_is_empty(top, IVec3::splat(i32::MAX)));
+        assert!(csg.region_is_empty(IVec3::ZERO, IVec3::splat(100)));
+        assert!(csg.intersects(&csg));
+        assert_eq!(csg.intersection(&csg).count_filled(), 27);
+        assert_eq!(csg.crop(top, top + IVec3::ONE).count_filled(), 8);
+
+        let mut bottom = VoxelCSG::new(4);
+        bottom.fill_cube(IVec3::splat(i32::MIN), IVec3::splat(i32::MIN + 2));
+        assert_eq!(bottom.count_filled(), 8);
+        assert!(bottom.get_voxel(IVec3::splat(i32::MIN)));
+        assert!(!bottom.intersects(&csg));
+    }
+
+    #[test]
+    fn test_revolve_at_i32_limits() {
+        use crate::Axis;
+
+        // The half of the solid past i32::MAX is dropped; the rest matches
+        // the same revolve around the origin.
+        let profile = [(0,0), (3,0), (3,1), (0,1)];
+        let mut edge = VoxelCSG::new(4);
+        edge.fill_revolve(&profile, Axis::Z, IVec3::new(i32::MAX - 1, 0, 0), None);
+        let mut origin = VoxelCSG::new(4);
+        origin.fill_revolve(&profile, Axis::Z, IVec3::ZERO, None);
+        assert_eq!(edge.count_filled(), origin.iter_filled().filter(|c| c.x <= 1).count());
+        assert!(edge.get_voxel(IVec3::new(i32::MAX, 0, 1)));
+        assert!(edge.get_voxel(IVec3::new(i32::MAX - 4, 0, 0)));
+    }
+
+    #[test]
+    fn test_count_filled_saturates() {
+        // A single fill_cube of the whole level-31 root region below the
+        // origin stores it as one summarizing node of 2^93 voxels.
+        let mut csg = VoxelCSG::new(32);
+        csg.fill_cube(IVec3::splat(i32::MIN), IVec3::ZERO);
+        assert_eq!(csg.count_filled(), usize::MAX);
+
+        let mut big = VoxelCSG::new(4);
+        big.fill_cube(IVec3::ZERO, IVec3::splat(1 << 20));
+        assert_eq!(big.count_filled() as u128, 1u128 << 60);
+    }
+
+    // ------------------------------------------------------------
+    // 24) Edit history
+    // ------------------------------------------------------------
+    #[test]
+    fn test_edit_log() {
+        let mut csg = VoxelCSG::new(5);
+        csg.fill_cube(IVec3::new(0,0,0), IVec3::new(2,2,2));
+        let initial = csg.clone();
+
+        csg.begin_recording();
+        csg.fill_cube(IVec3::new(1,0,0), IVec3::new(3,1,1));
+        csg.set_voxel(IVec3::new(0,0,0), false);
+        let log = csg.take_log();
+        assert_eq!(log.entries.len(), 3);
+        assert_eq!(log.entries[2], (IVec3::new(0,0,0), true, false));
+        assert_ne!(csg, initial);
+
+        // Replaying the log backwards restores the initial shape.
+        for (coords, old, _) in log.entries.iter().rev() {
+            csg.set_voxel(*coords, *old);
+        }
+        assert_eq!(csg, initial);
+
+        log.redo(&mut csg);
+        assert!(csg.get_voxel(IVec3::new(2,0,0)));
+        assert!(!csg.get_voxel(IVec3::new(0,0,0)));
+        log.undo(&mut csg);
+        assert_eq!(csg, initial);
+
+        // Nothing is recorded once the log has been taken.
+        csg.set_voxel(IVec3::new(4,4,4), true);
+        assert!(csg.take_log().entries.is_empty());
+    }
+
+    #[test]
+    fn test_apply_ops() {
+        use crate::ParseError;
+
+        let shape = VoxelCSG::apply_ops("cube 0 0 0 4 4 4\nsphere 6 2 2 2.5").unwrap();
+        let mut cube = VoxelCSG::new(5);
+        cube.fill_cube(IVec3::new(0,0,0), IVec3::new(4,4,4));
+        let mut sphere = VoxelCSG::new(5);
+        sphere.fill_sphere(IVec3::new(6,2,2), 2.5);
+        assert_eq!(shape, cube.union(&sphere));
+
+        let script = "# a cube with a bite out of it\ncube 0 0 0 4 4 4\n\nsphere 4 4 4 2\ndifference\n";
+        assert_eq!(VoxelCSG::apply_ops(script).unwrap(), cube.difference(&{
+            let mut s = VoxelCSG::new(5);
+            s.fill_sphere(IVec3::new(4,4,4), 2.0);
+            s
+        }));
+
+        assert_eq!(VoxelCSG::apply_ops("").unwrap().count_filled(), 0);
+        assert_eq!(
+            VoxelCSG::apply_ops("cube 0 0 0 1 1 1\ncone 1 2 3").unwrap_err(),
+            ParseError { line: 2, message: "unknown command `cone`".into() }
+        );
+        assert_eq!(VoxelCSG::apply_ops("sphere 0 0 x 1").unwrap_err().line, 1);
+        assert_eq!(VoxelCSG::apply_ops("cube 0 0 0 1 1 1\ncube 2 0 0 1 1 1").unwrap_err().line, 2);
+        assert!(VoxelCSG::apply_ops("cube 0 0 0 1 1 1\nunion").is_err());
+    }
+
+    #[test]
+    fn test_transaction() {
+        // 1000 scattered writes from a small LCG, with some clears and
+        // repeated coordinates mixed in.
+        let mut state = 12345u32;
+        let mut next = move || {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            (state >> 8) as i32
+        };
+        let writes: Vec<(IVec3, bool)> = (0..1000)
+            .map(|_| {
+                let p = IVec3::new(next() % 40 - 20, next() % 40 - 20, next() % 40 - 20);
+                (p, next() % 4 != 0)
+            })
+            .collect();
+
+        let mut individual = VoxelCSG::new(6);
+        for &(p, value) in &writes {
+            individual.set_voxel(p, value);
+        }
+
+        let mut batched = VoxelCSG::new(6);
+        batched.transaction(|tx| {
+            for &(p, value) in &writes {
+                if value {
+                    tx.set(p, true);
+                } else {
+                    tx.clear(p);
+                }
+            }
+        });
+
+        assert!(individual.count_filled() > 0);
+        assert_eq!(batched, individual);
+    }
+
+    #[test]
+    fn test_recorded_fill_cube_matches_scanline() {
+        let (min, max) = (IVec3::new(-5,-3,-7), IVec3::new(6,9,4));
+        let mut scanline = VoxelCSG::new(3);
+        for z in min.z..max.z {
+            for y in min.y..max.y {
+                for x in min.x..max.x {
+                    scanline.set_voxel(IVec3::new(x,y,z), true);
+                }
+            }
+        }
+
+        let mut recorded = VoxelCSG::new(3);
+        recorded.begin_recording();
+        recorded.fill_cube(min, max);
+        let log = recorded.take_log();
+        assert_eq!(recorded, scanline);
+        assert_eq!(recorded.count_filled(), 11 * 12 * 11);
+
+        // Every voxel is logged exactly once, whatever the order.
+        let mut logged: Vec<_> = log.entries.iter().map(|e| (e.0.z, e.0.y, e.0.x)).collect();
+        let mut expected: Vec<_> = scanline.iter_filled().map(|c| (c.z, c.y, c.x)).collect();
+        logged.sort();
+        expected.sort();
+        assert_eq!(logged, expected);
+
+        log.undo(&mut recorded);
+        assert_eq!(recorded.count_filled(), 0);
+    }
+
+    // ------------------------------------------------------------
+    // 25) Serialization and dense data
+    // ------------------------------------------------------------
+    #[test]
+    fn test_to_from_bytes() {
+        use crate::DecodeError;
+
+        let mut csg = VoxelCSG::new(6);
+        csg.fill_cube(IVec3::new(-4,0,0), IVec3::new(4,4,4));
+        csg.fill_sphere(IVec3::new(10,10,10), 3.0);
+        csg.set_voxel(IVec3::new(0,1,1), false);
+
+        let bytes = csg.to_bytes();
+        assert_eq!(&bytes[..4], b"VXRL");
+        assert_eq!(bytes[5], 6);
+        // Byte-stable: an equal shape built differently encodes identically.
+        let copy = VoxelCSG::from_coords(csg.iter_filled());
+        let mut copy6 = VoxelCSG::new(6);
+        copy6.union_with(&copy);
+        assert_eq!(copy6.to_bytes(), bytes);
+
+        let decoded = VoxelCSG::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, csg);
+        assert_eq!(decoded.height(), 6);
+        assert_eq!(decoded.to_bytes(), bytes);
+
+        assert_eq!(VoxelCSG::from_bytes(b"nope").unwrap_err(), DecodeError::BadMagic);
+        assert_eq!(VoxelCSG::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(), DecodeError::Truncated);
+        let empty = VoxelCSG::new(3).to_bytes();
+        assert_eq!(empty.len(), 10);
+        assert_eq!(VoxelCSG::from_bytes(&empty).unwrap().count_filled(), 0);
+
+        // Header with a given height and run count, followed by `runs`.
+        let blob = |height: u8, run_count: u32, runs: &[(u64, u32)]| {
+            let mut bytes = bytes[..5].to_vec();
+            bytes.push(height);
+            bytes.extend_from_slice(&run_count.to_le_bytes());
+            for (start, len) in runs {
+                bytes.extend_from_slice(&start.to_le_bytes());
+                bytes.extend_from_slice(&len.to_le_bytes());
+            }
+            bytes
+        };
+        let decode = |bytes: Vec<u8>| VoxelCSG::from_bytes(&bytes).map(|csg| csg.count_filled());
+        // Billions of declared runs backed by one.
+        assert_eq!(decode(blob(32, u32::MAX, &[(0, 1)])), Err(DecodeError::Truncated));
+        // A run of four billion voxels decodes as a few dozen blocks.
+        assert_eq!(decode(blob(32, 1, &[(0, u32::MAX)])), Ok(u32::MAX as usize));
+        // Voxels around -2^20 don't fit a height-4 tree, and a run can't pass
+        // the last Morton code.
+        assert_eq!(decode(blob(4, 1, &[(0, 1)])), Err(DecodeError::InvalidExtents));
+        assert_eq!(decode(blob(32, 1, &[((1 << 63) - 1, 2)])), Err(DecodeError::InvalidRun));
+    }
+
+    #[test]
+    fn test_rle_round_trip() {
+        use crate::DecodeError;
+
+        // A large solid cube, deliberately not aligned to octree nodes.
+        let mut cube = VoxelCSG::new(7);
+        cube.fill_cube(IVec3::new(-21,-13,-7), IVec3::new(19,27,33));
+        let rle = cube.to_rle_bytes();
+        assert!(rle.len() * 10 < cube.to_bytes().len(), "{} vs {}", rle.len(), cube.to_bytes().len());
+
+        let decoded = VoxelCSG::from_rle_bytes(&rle).unwrap();
+        assert_eq!(decoded, cube);
+        assert_eq!(decoded.height(), cube.height());
+
+        let path = std::env::temp_dir().join("voxelcsgrs_test_rle.vxrs");
+        let path = path.to_str().unwrap();
+        cube.save_to_file_rle(path).expect("Saving RLE failed");
+        assert_eq!(VoxelCSG::load_from_file_rle(path).unwrap(), cube);
+
+        // A gigavoxel solid block encodes from its summarized nodes: one empty
+        // and one filled run (1 + 3 varint bytes) per slice.
+        let mut block = VoxelCSG::new(12);
+        block.fill_cube(IVec3::ZERO, IVec3::splat(1024));
+        assert_eq!(block.to_rle_bytes().len(), 30 + 1024 * 4);
+
+        let empty = VoxelCSG::new(3);
+        assert_eq!(VoxelCSG::from_rle_bytes(&empty.to_rle_bytes()).unwrap().count_filled(), 0);
+        assert_eq!(VoxelCSG::from_rle_bytes(b"VXRL").unwrap_err(), DecodeError::BadMagic);
+        assert_eq!(VoxelCSG::from_rle_bytes(&rle[..rle.len() - 1]).unwrap_err(), DecodeError::Truncated);
+    }
+
+    #[test]
+    fn test_rle_rejects_malformed_input() {
+        use crate::DecodeError;
+
+        let mut shape = VoxelCSG::new(6);
+        shape.fill_sphere(IVec3::new(3,-2,5), 5.0);
+        shape.fill_cube(IVec3::new(-9,-3,-1), IVec3::new(-2,4,2));
+        let rle = shape.to_rle_bytes();
+        let (min, max) = shape.bounding_box().unwrap();
+
+        // Every strict prefix is cut short somewhere.
+        for len in 0..rle.len() {
+            let expected = if len < 4 { DecodeError::BadMagic } else { DecodeError::Truncated };
+            assert_eq!(VoxelCSG::from_rle_bytes(&rle[..len]).unwrap_err(), expected, "prefix of {}", len);
+        }
+
+        // Header with a given height, minimum and size, followed by `runs`.
+        let blob = |height: u8, min: [i32; 3], size: [u32; 3], runs: &[u8]| {
+            let mut bytes = rle[..5].to_vec();
+            bytes.push(height);
+            min.iter().for_each(|c| bytes.extend_from_slice(&c.to_le_bytes()));
+            size.iter().for_each(|c| bytes.extend_from_slice(&c.to_le_bytes()));
+            bytes.extend_from_slice(runs);
+            bytes
+        };
+        let decode = |bytes: Vec<u8>| VoxelCSG::from_rle_bytes(&bytes).map(|csg| csg.count_filled());
+        assert_eq!(decode(blob(4, [0, 0, 0], [2, 2, 1], &[1, 3])), Ok(3));
+        // Boxes that leave the tree, or are empty on only some axes.
+        assert_eq!(decode(blob(4, [0, 0, 0], [9, 1, 1], &[0, 9])), Err(DecodeError::InvalidExtents));
+        assert_eq!(decode(blob(4, [-9, 0, 0], [1, 1, 1], &[0, 1])), Err(DecodeError::InvalidExtents));
+        assert_eq!(decode(blob(32, [i32::MAX, 0, 0], [2, 1, 1], &[0, 2])), Err(DecodeError::InvalidExtents));
+        assert_eq!(decode(blob(4, [0, 0, 0], [0, 2, 2], &[])), Err(DecodeError::InvalidExtents));
+        // Billions of declared slices backed by a handful of bytes.
+        assert_eq!(decode(blob(32, [0, 0, -1 << 30], [1, 1, 1 << 31], &[0, 1, 0, 1])), Err(DecodeError::Truncated));
+        // A run past the end of its slice, and a varint wider than 64 bits.
+        assert_eq!(decode(blob(4, [0, 0, 0], [2, 2, 1], &[1, 4])), Err(DecodeError::InvalidRun));
+        assert_eq!(decode(blob(4, [0, 0, 0], [2, 2, 1], &[0xff; 11])), Err(DecodeError::InvalidRun));
+
+        // Scrambling the runs never panics, and anything that still decodes
+        // stays within the declared box.
+        let mut state = 2024u32;
+        let mut next = move || {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            (state >> 8) as usize
+        };
+        for _ in 0..500 {
+            let mut bytes = rle.clone();
+            for _ in 0..1 + next() % 3 {
+                let i = 30 + next() % (bytes.len() - 30);
+                bytes[i] = next() as u8;
+            }
+            if let Ok(decoded) = VoxelCSG::from_rle_bytes(&bytes) {
+                if let Some((lo, hi)) = decoded.bounding_box() {
+                    assert!(lo.cmpge(min).all() && hi.cmple(max).all());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_dense() {
+        use grid_tree::glam::UVec3;
+
+        // X fastest: indices 0..8 are (0,0,0), (1,0,0), (0,1,0), (1,1,0), (0,0,1), ...
+        let data = [true, false, false, true, false, true, true, false];
+        let origin = IVec3::new(10,-4,0);
+        let csg = VoxelCSG::from_dense(&data, UVec3::new(2,2,2), origin);
+
+        let mut filled: Vec<IVec3> = csg.iter_filled().map(|c| c - origin).collect();
+        filled.sort_by_key(|c| (c.z, c.y, c.x));
+        assert_eq!(filled, vec![
+            IVec3::new(0,0,0), IVec3::new(1,1,0), IVec3::new(1,0,1), IVec3::new(0,1,1),
+        ]);
+        assert!(csg.try_get_voxel(origin + IVec3::ONE).is_ok());
+
+        let empty = VoxelCSG::from_dense(&[false; 8], UVec3::new(2,2,2), IVec3::new(100,0,0));
+        assert_eq!(empty.count_filled(), 0);
+        assert!(empty.try_get_voxel(IVec3::new(101,1,1)).is_ok());
+
+        // A grid ending exactly on the i32 limits.
+        let edge = VoxelCSG::from_dense(&[true; 2], UVec3::new(2,1,1), IVec3::new(i32::MAX - 1, i32::MIN, 0));
+        assert_eq!(edge.height(), 32);
+        assert!(edge.get_voxel(IVec3::new(i32::MAX, i32::MIN, 0)));
+    }
+
+    #[test]
+    #[should_panic(expected = "data length does not match dims")]
+    fn test_from_dense_rejects_wrong_length() {
+        use grid_tree::glam::UVec3;
+
+        // 2^16 cubed wraps to 0 in u32, matching an empty slice.
+        VoxelCSG::from_dense(&[], UVec3::splat(1 << 16), IVec3::ZERO);
+    }
+
+    #[test]
+    #[should_panic(expected = "grid reaches past the i32 range")]
+    fn test_from_dense_rejects_grid_past_i32() {
+        use grid_tree::glam::UVec3;
+
+        VoxelCSG::from_dense(&[true; 3], UVec3::new(3,1,1), IVec3::new(i32::MAX - 1, 0, 0));
+    }
+
+    #[test]
+    fn test_to_dense() {
+        let mut shape = VoxelCSG::new(5);
+        shape.fill_sphere(IVec3::new(2,2,2), 3.0);
+        let (min, max) = (IVec3::new(0,-1,1), IVec3::new(4,3,6));
+
+        let data = shape.to_dense(min, max);
+        assert_eq!(data.len(), 5 * 5 * 6);
+        assert!(data[0] == shape.get_voxel(min));
+
+        let dims = (max - min + IVec3::ONE).as_uvec3();
+        let round_trip = VoxelCSG::from_dense(&data, dims, min);
+        assert_eq!(round_trip, shape.crop(min, max));
+        assert!(shape.to_dense(IVec3::ONE, IVec3::ZERO).is_empty());
+
+        // A window onto the corner of a huge summarized block.
+        let mut big = VoxelCSG::new(4);
+        big.fill_cube(IVec3::ZERO, IVec3::splat(1 << 20));
+        let window = big.to_dense(IVec3::splat(-1), IVec3::splat(1));
+        assert_eq!(window.iter().filter(|&&filled| filled).count(), 8);
+        // Index 13 is (0,0,0), index 12 is (-1,0,0).
+        assert!(window[13] && !window[12]);
+        // Far from the origin, near the i32 limits.
+        let far = big.to_dense(IVec3::splat(i32::MAX - 1), IVec3::splat(i32::MAX));
+        assert_eq!(far, vec![false; 8]);
+    }
+
+    #[test]
+    fn test_point_cloud() {
+        use grid_tree::glam::Vec3;
+
+        let mut csg = VoxelCSG::new(4);
+        csg.fill_cube(IVec3::new(0,0,0), IVec3::new(3,2,2));
+        csg.set_voxel(IVec3::new(-5,6,-7), true);
+
+        let points = csg.to_point_cloud();
+        assert_eq!(points.len(), csg.count_filled());
+        assert!(points.contains(&Vec3::new(-5.0, 6.0, -7.0)));
+        assert!(points.contains(&Vec3::new(2.0, 1.0, 1.0)));
+
+        let path = std::env::temp_dir().join("voxelcsgrs_test_points.ply");
+        csg.save_to_ply(path.to_str().unwrap()).expect("Saving PLY failed");
+        let ply = std::fs::read_to_string(&path).unwrap();
+        assert!(ply.starts_with("ply\nformat ascii 1.0\nelement vertex 13\n"));
+        assert!(ply.lines().any(|line| line == "-5 6 -7"));
+        assert_eq!(ply.lines().skip_while(|&line| line != "end_header").count(), 1 + 13);
+    }
+
+    #[test]
+    fn test_save_to_magicavoxel_centered() {
+        let mut csg = VoxelCSG::new(4);
+        csg.fill_cube(IVec3::new(-6,-2,0), IVec3::new(4,2,3));
+        let path = std::env::temp_dir().join("voxelcsgrs_test_centered.vox");
+        let path = path.to_str().unwrap();
+
+        let offset = csg.save_to_magicavoxel_centered(path, 64).expect("Saving centered failed");
+        // Every voxel lands inside the grid, with equal margins (to within
+        // one voxel) on each side.
+        let (min, max) = csg.bounding_box().unwrap();
+        assert!(csg.iter_filled().all(|p| {
+            let q = p + offset;
+            q.cmpge(IVec3::ZERO).all() && q.cmplt(IVec3::splat(64)).all()
+        }));
+        let (low, high) = (min + offset, IVec3::splat(63) - (max + offset));
+        assert!((low - high).abs().max_element() <= 1);
+        assert_eq!(min + offset, IVec3::new(27, 30, 30));
+
+        let err = csg.save_to_magicavoxel_centered(path, 8).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_save_slice_svg() {
+        use crate::Axis;
+
+        let mut csg = VoxelCSG::new(5);
+        csg.fill_disc(IVec3::new(2,-1,3), 6.0, Axis::Z);
+        let contours = csg.slice_contours(Axis::Z, 3);
+        assert_eq!(contours.len(), 1);
+        // Every outline point sits about 6.5 voxels from the center.
+        for p in &contours[0] {
+            let r = (p.x - 2.0).hypot(p.y + 1.0);
+            assert!((5.5..=7.0).contains(&r), "{:?} at distance {}", p, r);
+        }
+        assert!(csg.slice_contours(Axis::Z, 4).is_empty());
+
+        let path = std::env::temp_dir().join("voxelcsgrs_test_slice.svg");
+        let path = path.to_str().unwrap();
+        csg.save_slice_svg(Axis::Z, 3, path).unwrap();
+        let svg = std::fs::read_to_string(path).unwrap();
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches("<path").count(), 1);
+        let d = svg.split("d=\"").nth(1).unwrap().split('"').next().unwrap();
+        assert!(d.starts_with('M') && d.ends_with('Z'));
+        assert_eq!(d.matches('L').count() + 1, contours[0].len());
+        std::fs::remove_file(path).unwrap();
+
+        // A ring has an outer contour and a hole.
+        let mut ring = VoxelCSG::new(4);
+        ring.fill_cube(IVec3::new(0,0,0), IVec3::new(5,5,1));
+        ring.set_voxel(IVec3::new(2,2,0), false);
+        assert_eq!(ring.slice_contours(Axis::Z, 0).len(), 2);
+    }
+
+    // ------------------------------------------------------------
+    // 26) Meshing
+    // ------------------------------------------------------------
+    #[test]
+    fn test_to_mesh_lod() {
+        use grid_tree::glam::Vec3;
+
+        let mut cube = VoxelCSG::new(6);
+        cube.fill_cube(IVec3::new(0,0,0), IVec3::new(16,16,16));
+
+        // Stored as one solid block: six faces, as greedy meshing would give.
+        let lod = cube.to_mesh_lod(1);
+        assert_eq!(lod.triangle_count(), 12);
+        assert!(lod.triangle_count() < 16 * 16 * 16 * 12);
+
+        // Full detail meshes every exposed voxel face.
+        let detailed = cube.to_mesh_lod(u32::MAX);
+        assert_eq!(detailed.triangle_count(), 6 * 16 * 16 * 2);
+
+        // Both cover the same box.
+        for mesh in [&lod, &detailed] {
+            let min = mesh.positions.iter().fold(Vec3::splat(100.0), |a, &p| a.min(p));
+            let max = mesh.positions.iter().fold(Vec3::splat(-100.0), |a, &p| a.max(p));
+            assert_eq!(min, Vec3::splat(-0.5));
+            assert_eq!(max, Vec3::splat(15.5));
+        }
+
+        // A cube not aligned to the octree still meshes to its surface only.
+        let mut shifted = VoxelCSG::new(6);
+        shifted.fill_cube(IVec3::new(1,1,1), IVec3::new(5,5,5));
+        let mesh = shifted.to_mesh_lod(0);
+        assert!(mesh.triangle_count() <= 6 * 4 * 4 * 2);
+        assert!(mesh.triangle_count() >= 12);
+
+        // Huge blocks are merged without visiting their faces voxel by voxel,
+        // even at the i32 limits.
+        let mut big = VoxelCSG::new(4);
+        big.fill_cube(IVec3::ZERO, IVec3::splat(1 << 20));
+        assert_eq!(big.to_mesh_lod(0).triangle_count(), 12);
+        let mut corner = VoxelCSG::new(32);
+        corner.fill_cube(IVec3::splat(i32::MIN), IVec3::ZERO);
+        assert_eq!(corner.to_mesh_lod(0).triangle_count(), 12);
+        let mut edge = VoxelCSG::new(4);
+        edge.set_voxel(IVec3::splat(i32::MAX), true);
+        assert_eq!(edge.to_mesh_lod(0).triangle_count(), 12);
+    }
+
+    #[test]
+    fn test_mesh_simplify() {
+        let area = |mesh: &crate::Mesh| -> f32 {
+            mesh.indices.chunks(3).map(|t| {
+                let [a, b, c] = [t[0], t[1], t[2]].map(|i| mesh.positions[i as usize]);
+                (b - a).cross(c - a).length() / 2.0
+            }).sum()
+        };
+
+        // A 16x16x1 wall meshed one voxel face at a time.
+        let mut wall = VoxelCSG::new(5);
+        wall.fill_cube(IVec3::new(0,0,0), IVec3::new(16,16,1));
+        let mut mesh = wall.to_mesh_lod(u32::MAX);
+        assert!(mesh.triangle_count() > 500);
+        let before = area(&mesh);
+        mesh.simplify();
+        // Two triangles per side of the slab.
+        assert_eq!(mesh.triangle_count(), 12);
+        assert!((area(&mesh) - before).abs() < 1e-3);
+
+        // An L-shaped face needs two rectangles; simplifying again is a no-op.
+        let mut l = VoxelCSG::new(4);
+        l.fill_cube(IVec3::new(0,0,0), IVec3::new(4,1,1));
+        l.fill_cube(IVec3::new(0,1,0), IVec3::new(1,4,1));
+        let mut mesh = l.to_mesh_lod(u32::MAX);
+        let before = area(&mesh);
+        mesh.simplify();
+        let top = mesh.indices.chunks(3)
+            .filter(|t| t.iter().all(|&i| mesh.positions[i as usize].z == 0.5))
+            .count();
+        assert_eq!(top, 4);
+        assert!((area(&mesh) - before).abs() < 1e-3);
+        let once = mesh.clone();
+        mesh.simplify();
+        assert_eq!(mesh.triangle_count(), once.triangle_count());
+    }
+
+    #[test]
+    fn test_mesh_scale() {
+        use grid_tree::glam::Vec3;
+
+        let mut csg = VoxelCSG::new(3);
+        csg.set_voxel(IVec3::new(1,0,0), true);
+        let unit = csg.to_mesh_lod(u32::MAX);
+        let mut mesh = unit.clone();
+        mesh.scale(Vec3::ONE);
+        assert_eq!(mesh, unit);
+
+        mesh.scale(Vec3::new(1.0, 1.0, 2.0));
+        let lo = mesh.positions.iter().fold(Vec3::splat(f32::MAX), |lo, &p| lo.min(p));
+        let hi = mesh.positions.iter().fold(Vec3::splat(f32::MIN), |hi, &p| hi.max(p));
+        let extent = hi - lo;
+        assert_eq!(extent.z, 2.0 * extent.x);
+        assert_eq!(extent, Vec3::new(1.0, 1.0, 2.0));
+        assert_eq!((lo.x, hi.x), (0.5, 1.5));
+        assert_eq!(mesh.indices, unit.indices);
+    }
+
+    #[test]
+    fn test_save_to_obj_with_materials() {
+        // Two adjacent voxels of different materials.
+        let mut csg = VoxelCSG::<u8>::with_height(3);
+        csg.set(IVec3::new(0,0,0), 1);
+        csg.set(IVec3::new(1,0,0), 2);
+
+        let meshes = csg.to_meshes_by_material(|&value| value as u32);
+        assert_eq!(meshes.len(), 2);
+        // Five exposed faces each; the shared face is not emitted.
+        assert_eq!((meshes[0].0, meshes[1].0), (1, 2));
+        assert_eq!(meshes[0].1.triangle_count(), 10);
+        assert_eq!(meshes[1].1.triangle_count(), 10);
+
+        let dir = std::env::temp_dir().join("voxelcsgrs_test_obj");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("two_materials.obj");
+        csg.save_to_obj_with_materials(path.to_str().unwrap(), |&value| value as u32)
+            .expect("Saving OBJ failed");
+
+        let obj = std::fs::read_to_string(&path).unwrap();
+        assert!(obj.starts_with("mtllib two_materials.mtl"));
+        let used: std::collections::HashSet<&str> =
+            obj.lines().filter(|line| line.starts_with("usemtl ")).collect();
+        assert_eq!(used.len(), 2);
+        assert_eq!(obj.lines().filter(|line| line.starts_with("f ")).count(), 20);
+
+        let mtl = std::fs::read_to_string(dir.join("two_materials.mtl")).unwrap();
+        assert!(mtl.contains("newmtl material_1") && mtl.contains("newmtl material_2"));
+    }
 }
 